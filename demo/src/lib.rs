@@ -113,9 +113,14 @@ pub async fn worker_entry() {
     log!("worker thread closing");
 }
 
-fn convert_coord(canvas: &web_sys::HtmlElement, event: &web_sys::Event) -> [f32; 2] {
+fn convert_coord(canvas: &web_sys::EventTarget, event: &web_sys::Event) -> [f32; 2] {
     use wasm_bindgen::JsCast;
-    shogo::simple2d::convert_coord(canvas, event.dyn_ref().unwrap_throw())
+    let event: &web_sys::MouseEvent = event.dyn_ref().unwrap_throw();
+    shogo::input::convert_coord(
+        canvas.dyn_ref().unwrap_throw(),
+        [event.client_x() as f32, event.client_y() as f32],
+        shogo::input::Backing::Attributes,
+    )
 }
 
 fn projection(dim: [f32; 2], offset: [f32; 2]) -> [f32; 16] {
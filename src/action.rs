@@ -0,0 +1,497 @@
+//!
+//! Map physical inputs to named game actions and axes.
+//!
+//! ```ignore
+//! let mut bindings = Bindings::default();
+//! bindings.bind("jump", Binding::Key("Space".to_string()));
+//! bindings.bind("jump", Binding::GamepadButton { pad: None, button: 0 });
+//! bindings.bind_axis(
+//!     "move_x",
+//!     AxisBinding::Keys {
+//!         negative: "KeyA".to_string(),
+//!         positive: "KeyD".to_string(),
+//!     },
+//! );
+//!
+//! let mut actions = ActionMap::new(bindings);
+//! loop {
+//!     for e in events.drain(..) {
+//!         match e {
+//!             MEvent::Input(e) => actions.handle(&e),
+//!             MEvent::Gamepad(e) => actions.handle_gamepad(&e),
+//!         }
+//!     }
+//!     if actions.action_just_pressed("jump") {
+//!         jump();
+//!     }
+//!     player.x += actions.axis("move_x");
+//!     actions.end_frame();
+//! }
+//! ```
+//!
+use crate::gamepad::GamepadEvent;
+use crate::input::{InputEvent, MouseButton};
+use crate::input_state::InputState;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+///
+/// A physical input that can trigger an action.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Binding {
+    ///
+    /// A key identified by `KeyboardEvent.code`.
+    ///
+    Key(String),
+    Mouse(MouseButton),
+    ///
+    /// A button on the gamepad at `pad`, or on any gamepad if `None`.
+    ///
+    GamepadButton { pad: Option<u32>, button: u32 },
+    ///
+    /// A gamepad axis pushed past `threshold`. A negative threshold
+    /// means the axis has to be pushed below it.
+    ///
+    GamepadAxis {
+        pad: Option<u32>,
+        axis: u32,
+        threshold: f32,
+    },
+    ///
+    /// A touch inside the rectangle `[x, y, width, height]`, in the same
+    /// coordinates as the forwarded touch events.
+    ///
+    TouchZone([f32; 4]),
+}
+
+///
+/// A physical input that contributes to an axis in the range `[-1,1]`.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AxisBinding {
+    Keys {
+        negative: String,
+        positive: String,
+    },
+    GamepadButtons {
+        pad: Option<u32>,
+        negative: u32,
+        positive: u32,
+    },
+    GamepadAxis {
+        pad: Option<u32>,
+        axis: u32,
+        invert: bool,
+    },
+}
+
+///
+/// The bindings for every action and axis. Can be stored and loaded with serde,
+/// and changed at runtime.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Bindings {
+    pub actions: BTreeMap<String, Vec<Binding>>,
+    pub axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl Bindings {
+    ///
+    /// Add a binding to an action.
+    ///
+    pub fn bind(&mut self, action: &str, binding: Binding) -> &mut Self {
+        self.actions
+            .entry(action.to_string())
+            .or_default()
+            .push(binding);
+        self
+    }
+
+    ///
+    /// Add a binding to an axis.
+    ///
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) -> &mut Self {
+        self.axes.entry(axis.to_string()).or_default().push(binding);
+        self
+    }
+
+    ///
+    /// Remove all bindings of an action.
+    ///
+    pub fn unbind(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    ///
+    /// Remove all bindings of an axis.
+    ///
+    pub fn unbind_axis(&mut self, axis: &str) {
+        self.axes.remove(axis);
+    }
+
+    fn action(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map(|a| &a[..]).unwrap_or(&[])
+    }
+}
+
+fn in_zone(rect: [f32; 4], pos: [f32; 2]) -> bool {
+    let [x, y, w, h] = rect;
+    pos[0] >= x && pos[0] < x + w && pos[1] >= y && pos[1] < y + h
+}
+
+fn axis_past(value: f32, threshold: f32) -> bool {
+    if threshold < 0.0 {
+        value <= threshold
+    } else {
+        value >= threshold
+    }
+}
+
+impl Binding {
+    fn pressed(&self, input: &InputState) -> bool {
+        match self {
+            Binding::Key(code) => input.pressed(code),
+            Binding::Mouse(b) => input.mouse_pressed(*b),
+            Binding::GamepadButton { pad, button } => input.gamepad_pressed(*pad, *button),
+            &Binding::GamepadAxis {
+                pad,
+                axis,
+                threshold,
+            } => axis_past(input.gamepad_axis(pad, axis), threshold),
+            Binding::TouchZone(rect) => input.touches().iter().any(|t| in_zone(*rect, t.pos)),
+        }
+    }
+
+    //Axes are not tracked across frames so they only contribute through `pressed`.
+    fn just_pressed(&self, input: &InputState) -> bool {
+        match self {
+            Binding::Key(code) => input.just_pressed(code),
+            Binding::Mouse(b) => input.mouse_just_pressed(*b),
+            Binding::GamepadButton { pad, button } => input.gamepad_just_pressed(*pad, *button),
+            Binding::GamepadAxis { .. } => false,
+            Binding::TouchZone(rect) => input
+                .touches_started()
+                .iter()
+                .any(|t| in_zone(*rect, t.pos)),
+        }
+    }
+
+    fn just_released(&self, input: &InputState) -> bool {
+        match self {
+            Binding::Key(code) => input.just_released(code),
+            Binding::Mouse(b) => input.mouse_just_released(*b),
+            Binding::GamepadButton { pad, button } => input.gamepad_just_released(*pad, *button),
+            Binding::GamepadAxis { .. } => false,
+            Binding::TouchZone(rect) => input
+                .touches_ended()
+                .iter()
+                .any(|t| in_zone(*rect, t.pos)),
+        }
+    }
+}
+
+impl AxisBinding {
+    fn value(&self, input: &InputState) -> f32 {
+        let dir = |neg: bool, pos: bool| (pos as i32 - neg as i32) as f32;
+        match self {
+            AxisBinding::Keys { negative, positive } => {
+                dir(input.pressed(negative), input.pressed(positive))
+            }
+            &AxisBinding::GamepadButtons {
+                pad,
+                negative,
+                positive,
+            } => dir(
+                input.gamepad_pressed(pad, negative),
+                input.gamepad_pressed(pad, positive),
+            ),
+            &AxisBinding::GamepadAxis { pad, axis, invert } => {
+                let v = input.gamepad_axis(pad, axis);
+                if invert {
+                    -v
+                } else {
+                    v
+                }
+            }
+        }
+    }
+}
+
+///
+/// Tracks input state and answers queries about actions and axes using [`Bindings`].
+///
+#[derive(Debug, Clone, Default)]
+pub struct ActionMap {
+    pub bindings: Bindings,
+    pub input: InputState,
+    held_last_frame: HashSet<String>,
+}
+
+impl ActionMap {
+    pub fn new(bindings: Bindings) -> Self {
+        ActionMap {
+            bindings,
+            input: InputState::new(),
+            held_last_frame: HashSet::new(),
+        }
+    }
+
+    pub fn handle(&mut self, event: &InputEvent) {
+        self.input.handle(event);
+    }
+
+    pub fn handle_gamepad(&mut self, event: &GamepadEvent) {
+        self.input.handle_gamepad(event);
+    }
+
+    ///
+    /// Clear the per frame state. Call once at the end of every frame.
+    ///
+    pub fn end_frame(&mut self) {
+        let held: HashSet<String> = self
+            .bindings
+            .actions
+            .keys()
+            .filter(|a| self.action_pressed(a))
+            .cloned()
+            .collect();
+        self.held_last_frame = held;
+        self.input.end_frame();
+    }
+
+    ///
+    /// True if any binding of the action is held.
+    ///
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.bindings
+            .action(action)
+            .iter()
+            .any(|b| b.pressed(&self.input))
+    }
+
+    ///
+    /// True if the action became active this frame.
+    ///
+    pub fn action_just_pressed(&self, action: &str) -> bool {
+        !self.held_last_frame.contains(action)
+            && (self.action_pressed(action)
+                || self
+                    .bindings
+                    .action(action)
+                    .iter()
+                    .any(|b| b.just_pressed(&self.input)))
+    }
+
+    ///
+    /// True if the action stopped being active this frame.
+    ///
+    pub fn action_just_released(&self, action: &str) -> bool {
+        !self.action_pressed(action)
+            && (self.held_last_frame.contains(action)
+                || self
+                    .bindings
+                    .action(action)
+                    .iter()
+                    .any(|b| b.just_released(&self.input)))
+    }
+
+    ///
+    /// The sum of all bindings of the axis, clamped to `[-1,1]`.
+    ///
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.bindings
+            .axes
+            .get(axis)
+            .map(|a| a.iter().map(|b| b.value(&self.input)).sum::<f32>())
+            .unwrap_or(0.0)
+            .clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Modifiers;
+
+    fn key_down(code: &str) -> InputEvent {
+        InputEvent::KeyDown {
+            code: code.to_owned(),
+            key: String::new(),
+            repeat: false,
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    fn key_up(code: &str) -> InputEvent {
+        InputEvent::KeyUp {
+            code: code.to_owned(),
+            key: String::new(),
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    fn pad_axis(axis: u32, value: f32) -> GamepadEvent {
+        GamepadEvent::Axis {
+            index: 0,
+            axis,
+            value,
+        }
+    }
+
+    fn jump() -> ActionMap {
+        let mut bindings = Bindings::default();
+        bindings
+            .bind("jump", Binding::Key("Space".to_owned()))
+            .bind(
+                "jump",
+                Binding::GamepadButton {
+                    pad: None,
+                    button: 0,
+                },
+            );
+        ActionMap::new(bindings)
+    }
+
+    #[test]
+    fn edges_across_frames() {
+        let mut a = jump();
+        a.handle(&key_down("Space"));
+        assert!(a.action_pressed("jump"));
+        assert!(a.action_just_pressed("jump"));
+        a.end_frame();
+
+        assert!(a.action_pressed("jump"));
+        assert!(!a.action_just_pressed("jump"));
+        a.handle(&key_up("Space"));
+        assert!(!a.action_pressed("jump"));
+        assert!(a.action_just_released("jump"));
+        a.end_frame();
+
+        assert!(!a.action_just_released("jump"));
+        assert!(!a.action_just_pressed("jump"));
+    }
+
+    #[test]
+    fn press_and_release_in_one_frame() {
+        let mut a = jump();
+        a.handle(&key_down("Space"));
+        a.handle(&key_up("Space"));
+        assert!(!a.action_pressed("jump"));
+        assert!(a.action_just_pressed("jump"));
+        assert!(a.action_just_released("jump"));
+        a.end_frame();
+
+        assert!(!a.action_just_pressed("jump"));
+        assert!(!a.action_just_released("jump"));
+    }
+
+    #[test]
+    fn second_binding_does_not_retrigger() {
+        let mut a = jump();
+        a.handle(&key_down("Space"));
+        a.end_frame();
+
+        a.handle_gamepad(&GamepadEvent::Button {
+            index: 1,
+            button: 0,
+            pressed: true,
+            value: 1.0,
+        });
+        assert!(!a.action_just_pressed("jump"));
+        a.handle(&key_up("Space"));
+        assert!(a.action_pressed("jump"));
+        assert!(!a.action_just_released("jump"));
+    }
+
+    #[test]
+    fn bind_and_unbind_at_runtime() {
+        let mut a = ActionMap::default();
+        a.handle(&key_down("KeyF"));
+        assert!(!a.action_pressed("fire"));
+
+        a.bindings.bind("fire", Binding::Key("KeyF".to_owned()));
+        assert!(a.action_pressed("fire"));
+        a.end_frame();
+
+        a.bindings.unbind("fire");
+        assert!(!a.action_pressed("fire"));
+        assert!(!a.bindings.actions.contains_key("fire"));
+    }
+
+    #[test]
+    fn axis_sums_and_clamps() {
+        let mut bindings = Bindings::default();
+        bindings
+            .bind_axis(
+                "move_x",
+                AxisBinding::Keys {
+                    negative: "KeyA".to_owned(),
+                    positive: "KeyD".to_owned(),
+                },
+            )
+            .bind_axis(
+                "move_x",
+                AxisBinding::GamepadAxis {
+                    pad: None,
+                    axis: 0,
+                    invert: true,
+                },
+            );
+        let mut a = ActionMap::new(bindings);
+        assert_eq!(a.axis("move_x"), 0.0);
+        assert_eq!(a.axis("missing"), 0.0);
+
+        a.handle(&key_down("KeyD"));
+        assert_eq!(a.axis("move_x"), 1.0);
+        a.handle_gamepad(&pad_axis(0, -0.5));
+        assert_eq!(a.axis("move_x"), 1.0);
+
+        a.handle(&key_up("KeyD"));
+        a.handle(&key_down("KeyA"));
+        assert_eq!(a.axis("move_x"), -0.5);
+
+        a.bindings.unbind_axis("move_x");
+        assert_eq!(a.axis("move_x"), 0.0);
+    }
+
+    #[test]
+    fn gamepad_axis_threshold() {
+        let mut bindings = Bindings::default();
+        bindings.bind(
+            "duck",
+            Binding::GamepadAxis {
+                pad: None,
+                axis: 1,
+                threshold: -0.5,
+            },
+        );
+        let mut a = ActionMap::new(bindings);
+        a.handle_gamepad(&pad_axis(1, -0.4));
+        assert!(!a.action_pressed("duck"));
+        a.handle_gamepad(&pad_axis(1, -0.6));
+        assert!(a.action_pressed("duck"));
+        assert!(a.action_just_pressed("duck"));
+    }
+
+    #[test]
+    fn bindings_serde_round_trip() {
+        let mut bindings = Bindings::default();
+        bindings
+            .bind("jump", Binding::Key("Space".to_owned()))
+            .bind("jump", Binding::Mouse(MouseButton::Left))
+            .bind("menu", Binding::TouchZone([0.0, 0.0, 10.0, 20.0]))
+            .bind_axis(
+                "move_y",
+                AxisBinding::GamepadButtons {
+                    pad: Some(0),
+                    negative: 12,
+                    positive: 13,
+                },
+            );
+
+        let json = serde_json::to_string(&bindings).unwrap();
+        let back: Bindings = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, bindings);
+    }
+}
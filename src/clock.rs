@@ -0,0 +1,203 @@
+//!
+//! Clocks that [`Timer`] can measure time and wait with.
+//!
+//! ```ignore
+//! //On the main thread.
+//! let mut timer = Timer::with_clock(60, WindowClock::new());
+//!
+//! //In a test, with simulated time.
+//! let clock = MockClock::new();
+//! let mut timer = Timer::with_clock(60, clock.clone());
+//! let info = futures::executor::block_on(timer.next());
+//! clock.advance(5.0);
+//! ```
+//!
+use super::*;
+use std::cell::Cell;
+
+///
+/// A source of time in milliseconds.
+///
+pub trait Clock {
+    fn now(&self) -> f64;
+
+    ///
+    /// Wait for the given number of milliseconds.
+    ///
+    fn sleep(&self, ms: f64) -> impl std::future::Future<Output = ()>;
+}
+
+///
+/// `performance.now()` of a worker. Only works inside of a worker.
+///
+#[derive(Debug, Clone)]
+pub struct WorkerClock {
+    performance: web_sys::Performance,
+}
+impl WorkerClock {
+    pub fn new() -> Self {
+        WorkerClock {
+            performance: utils::get_worker_global_context()
+                .performance()
+                .unwrap_throw(),
+        }
+    }
+}
+impl Default for WorkerClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Clock for WorkerClock {
+    fn now(&self) -> f64 {
+        self.performance.now()
+    }
+    fn sleep(&self, ms: f64) -> impl std::future::Future<Output = ()> {
+        TimeoutFuture::new(ms.round() as u32)
+    }
+}
+
+///
+/// `performance.now()` of the window. Only works on the main thread.
+///
+#[derive(Debug, Clone)]
+pub struct WindowClock {
+    performance: web_sys::Performance,
+}
+impl WindowClock {
+    pub fn new() -> Self {
+        WindowClock {
+            performance: gloo::utils::window().performance().unwrap_throw(),
+        }
+    }
+}
+impl Default for WindowClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Clock for WindowClock {
+    fn now(&self) -> f64 {
+        self.performance.now()
+    }
+    fn sleep(&self, ms: f64) -> impl std::future::Future<Output = ()> {
+        TimeoutFuture::new(ms.round() as u32)
+    }
+}
+
+///
+/// A simulated clock that only moves when told to. Sleeping jumps straight to the end
+/// of the sleep, so code using it runs instantly and deterministically.
+/// Clones share the same time.
+///
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    time: Rc<Cell<f64>>,
+}
+impl MockClock {
+    ///
+    /// A clock starting at 0.
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, time: f64) {
+        self.time.set(time);
+    }
+
+    pub fn advance(&self, ms: f64) {
+        self.time.set(self.time.get() + ms);
+    }
+}
+impl Clock for MockClock {
+    fn now(&self) -> f64 {
+        self.time.get()
+    }
+    fn sleep(&self, ms: f64) -> impl std::future::Future<Output = ()> {
+        self.advance(ms.max(0.0));
+        std::future::ready(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    const PERIOD: f64 = 1000.0 / 60.0;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn mock_clock_sleep_advances() {
+        let clock = MockClock::new();
+        block_on(clock.sleep(5.0));
+        assert_eq!(clock.now(), 5.0);
+        clock.advance(2.0);
+        assert_eq!(clock.clone().now(), 7.0);
+    }
+
+    #[test]
+    fn timer_frames() {
+        let clock = MockClock::new();
+        let mut timer = Timer::with_clock(60, clock.clone());
+
+        let info = block_on(timer.next());
+        assert_eq!(info.index, 0);
+        assert_eq!(info.delta, 0.0);
+        assert_eq!(info.lateness, 0.0);
+        assert_eq!(info.skipped, 0);
+
+        let info = block_on(timer.next());
+        assert_eq!(info.index, 1);
+        assert!(close(info.time, PERIOD));
+        assert!(close(info.delta, PERIOD));
+        assert_eq!(info.lateness, 0.0);
+        assert_eq!(info.skipped, 0);
+
+        //The frame ran long, so the next one is late and one frame is skipped.
+        clock.advance(40.0);
+        let info = block_on(timer.next());
+        assert_eq!(info.index, 2);
+        assert!(close(info.delta, 40.0));
+        assert!(close(info.lateness, PERIOD + 40.0 - 2.0 * PERIOD));
+        assert_eq!(info.skipped, 1);
+
+        //Back on schedule.
+        let info = block_on(timer.next());
+        assert_eq!(info.index, 3);
+        assert!(close(info.time, 4.0 * PERIOD));
+        assert_eq!(info.lateness, 0.0);
+        assert_eq!(info.skipped, 0);
+    }
+
+    #[test]
+    fn set_frame_rate_moves_due() {
+        let clock = MockClock::new();
+        let mut timer = Timer::with_clock(60, clock.clone());
+
+        //No frame yet, so the first one is still due right away.
+        timer.set_frame_rate(30);
+        assert_eq!(timer.due, 0.0);
+
+        let info = block_on(timer.next());
+        timer.set_frame_rate(60);
+        assert!(close(timer.due, info.time + PERIOD));
+
+        //Slowing down pushes the next frame back.
+        timer.set_frame_rate(30);
+        assert!(close(timer.due, info.time + 2.0 * PERIOD));
+        assert!(close(timer.period(), 2.0 * PERIOD));
+
+        //If one new period after the last frame has passed, it's due now.
+        clock.advance(100.0);
+        timer.set_frame_rate(60);
+        assert_eq!(timer.due, clock.now());
+        let info = block_on(timer.next());
+        assert_eq!(info.lateness, 0.0);
+        assert!(close(info.time, 100.0));
+    }
+}
@@ -0,0 +1,364 @@
+//!
+//! Commands the worker can ask the main thread to carry out, for things only the DOM can do.
+//!
+//! Main thread:
+//! ```ignore
+//! let (sender, mut recv) = shogo::main::create_main("./worker.js", offscreen).await;
+//! recv.install_command_handler(CommandHandler::new(&canvas));
+//! ```
+//!
+//! Worker:
+//! ```ignore
+//! if sender.command(MainCommand::RequestPointerLock).await == CommandResult::Ok {
+//!     //...
+//! }
+//! //or, without waiting on the result
+//! sender.send_command(MainCommand::SetCursor("crosshair".to_string()));
+//! ```
+//!
+//! Pointer lock, fullscreen, the clipboard, vibration and opening new tabs all require
+//! a recent user gesture, so they should be issued promptly in response to input.
+//!
+use super::*;
+use std::collections::HashMap;
+
+///
+/// A command to run on the main thread.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum MainCommand {
+    ///
+    /// Lock the pointer to the handler's element. The result is [`CommandResult::Denied`]
+    /// if the browser refused.
+    ///
+    RequestPointerLock,
+    ExitPointerLock,
+    ///
+    /// Make the handler's element fullscreen.
+    ///
+    RequestFullscreen,
+    ExitFullscreen,
+    ///
+    /// Set the css `cursor` of the handler's element.
+    ///
+    SetCursor(String),
+    SetTitle(String),
+    ///
+    /// Vibrate with a pattern of alternating on and off durations in milliseconds.
+    ///
+    Vibrate(Vec<u32>),
+    OpenUrl {
+        url: String,
+        new_tab: bool,
+    },
+    WriteClipboard(String),
+    ///
+    /// Focus the handler's [`text::TextInput`].
+    ///
+    FocusTextInput,
+    BlurTextInput,
+    ///
+    /// Move the handler's [`text::TextInput`] over `[x, y, width, height]`, in css pixels
+    /// relative to the handler's element, e.g. over the text field being edited.
+    ///
+    SetTextInputRect([f32; 4]),
+    ///
+    /// Open the handler's [`file::FilePicker`]. `accept` is a list of file types
+    /// like `"image/*,.json"`, or empty for any.
+    ///
+    PickFiles {
+        accept: String,
+        multiple: bool,
+    },
+}
+
+///
+/// The outcome of a [`MainCommand`].
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CommandResult {
+    Ok,
+    ///
+    /// The browser refused, usually because there was no recent user gesture.
+    ///
+    Denied,
+    Err(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct CommandRequest {
+    pub id: u32,
+    pub command: MainCommand,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct CommandResponse {
+    pub id: u32,
+    pub result: CommandResult,
+}
+
+///
+/// Commands sent by the worker that are waiting on a response.
+///
+#[derive(Default)]
+pub(crate) struct Pending {
+    next_id: u32,
+    waiting: HashMap<u32, futures::channel::oneshot::Sender<CommandResult>>,
+}
+impl Pending {
+    pub fn respond(&mut self, r: CommandResponse) {
+        if let Some(s) = self.waiting.remove(&r.id) {
+            //The worker may have stopped waiting.
+            let _ = s.send(r.result);
+        }
+    }
+}
+
+///
+/// Runs [`MainCommand`]s sent from the worker. Installed with
+/// [`main::MainReceiver::install_command_handler`].
+///
+#[derive(Debug, Clone)]
+pub struct CommandHandler {
+    ///
+    /// The element used for pointer lock, fullscreen and the cursor.
+    ///
+    pub target: web_sys::HtmlElement,
+    ///
+    /// The input used by the text input commands.
+    ///
+    pub text_input: Option<text::TextInput>,
+    ///
+    /// The picker opened by [`MainCommand::PickFiles`].
+    ///
+    pub file_picker: Option<file::FilePicker>,
+}
+
+impl CommandHandler {
+    pub fn new(target: &web_sys::HtmlElement) -> Self {
+        CommandHandler {
+            target: target.clone(),
+            text_input: None,
+            file_picker: None,
+        }
+    }
+
+    pub fn with_text_input(mut self, text_input: text::TextInput) -> Self {
+        self.text_input = Some(text_input);
+        self
+    }
+
+    pub fn with_file_picker(mut self, file_picker: file::FilePicker) -> Self {
+        self.file_picker = Some(file_picker);
+        self
+    }
+
+    pub async fn execute(&self, command: MainCommand) -> CommandResult {
+        let document = gloo::utils::document();
+        let window = gloo::utils::window();
+
+        match command {
+            MainCommand::RequestPointerLock => {
+                let wait = wait_for(&document, "pointerlockchange", "pointerlockerror");
+                self.target.request_pointer_lock();
+                if wait.await && document.pointer_lock_element().is_some() {
+                    CommandResult::Ok
+                } else {
+                    CommandResult::Denied
+                }
+            }
+            MainCommand::ExitPointerLock => {
+                document.exit_pointer_lock();
+                CommandResult::Ok
+            }
+            MainCommand::RequestFullscreen => {
+                let wait = wait_for(&document, "fullscreenchange", "fullscreenerror");
+                if self.target.request_fullscreen().is_err() {
+                    return CommandResult::Denied;
+                }
+                if wait.await {
+                    CommandResult::Ok
+                } else {
+                    CommandResult::Denied
+                }
+            }
+            MainCommand::ExitFullscreen => {
+                if document.fullscreen_element().is_some() {
+                    document.exit_fullscreen();
+                }
+                CommandResult::Ok
+            }
+            MainCommand::SetCursor(cursor) => {
+                into_result(self.target.style().set_property("cursor", &cursor))
+            }
+            MainCommand::SetTitle(title) => {
+                document.set_title(&title);
+                CommandResult::Ok
+            }
+            MainCommand::Vibrate(pattern) => {
+                let pattern: js_sys::Array = pattern.into_iter().map(JsValue::from).collect();
+                if window.navigator().vibrate_with_pattern(&pattern) {
+                    CommandResult::Ok
+                } else {
+                    CommandResult::Denied
+                }
+            }
+            MainCommand::OpenUrl { url, new_tab } => {
+                let target = if new_tab { "_blank" } else { "_self" };
+                match window.open_with_url_and_target(&url, target) {
+                    Ok(Some(_)) => CommandResult::Ok,
+                    Ok(None) => CommandResult::Denied,
+                    Err(e) => err(e),
+                }
+            }
+            MainCommand::WriteClipboard(text) => {
+                let p = window.navigator().clipboard().write_text(&text);
+                into_result(wasm_bindgen_futures::JsFuture::from(p).await)
+            }
+            MainCommand::FocusTextInput => self.with_text(|t| t.focus()),
+            MainCommand::BlurTextInput => self.with_text(|t| t.blur()),
+            MainCommand::SetTextInputRect(rect) => {
+                self.with_text(|t| t.set_rect(&self.target, rect))
+            }
+            MainCommand::PickFiles { accept, multiple } => match &self.file_picker {
+                Some(p) => {
+                    p.open(&accept, multiple);
+                    CommandResult::Ok
+                }
+                None => CommandResult::Err("no file picker".to_string()),
+            },
+        }
+    }
+
+    fn with_text(
+        &self,
+        func: impl FnOnce(&text::TextInput) -> Result<(), JsValue>,
+    ) -> CommandResult {
+        match &self.text_input {
+            Some(t) => into_result(func(t)),
+            None => CommandResult::Err("no text input".to_string()),
+        }
+    }
+}
+
+fn err(e: JsValue) -> CommandResult {
+    CommandResult::Err(
+        e.as_string()
+            .or_else(|| {
+                e.dyn_ref::<js_sys::Error>()
+                    .map(|e| String::from(e.message()))
+            })
+            .unwrap_or_else(|| format!("{:?}", e)),
+    )
+}
+
+fn into_result<T>(r: Result<T, JsValue>) -> CommandResult {
+    match r {
+        Ok(_) => CommandResult::Ok,
+        Err(e) => err(e),
+    }
+}
+
+//Resolves to true if the `ok` event fires first, false if `error` does.
+//Listens right away so the events can't be missed.
+fn wait_for(
+    target: &web_sys::EventTarget,
+    ok: &'static str,
+    error: &'static str,
+) -> impl std::future::Future<Output = bool> {
+    let (s, r) = futures::channel::oneshot::channel();
+    let s = Rc::new(RefCell::new(Some(s)));
+
+    let listen = |event_type: &'static str, val: bool| {
+        let s = s.clone();
+        gloo::events::EventListener::once(target, event_type, move |_| {
+            if let Some(s) = s.borrow_mut().take() {
+                let _ = s.send(val);
+            }
+        })
+    };
+    let handles = (listen(ok, true), listen(error, false));
+
+    async move {
+        let res = r.await.unwrap_or(false);
+        drop(handles);
+        res
+    }
+}
+
+pub(crate) fn handle_request(
+    handler: &Rc<RefCell<Option<CommandHandler>>>,
+    worker: &Rc<RefCell<web_sys::Worker>>,
+    request: CommandRequest,
+) {
+    let handler = handler.borrow().clone();
+    let worker = worker.clone();
+
+    wasm_bindgen_futures::spawn_local(async move {
+        let result = match handler {
+            Some(h) => h.execute(request.command).await,
+            None => CommandResult::Err("no command handler installed".to_string()),
+        };
+
+        let response = ToWorker::CommandResponse(CommandResponse {
+            id: request.id,
+            result,
+        });
+
+        let (data, _) = internal_message(&response, None);
+        worker.borrow().post_message(&data).unwrap_throw();
+    });
+}
+
+impl<WM> main::MainReceiver<WM> {
+    ///
+    /// Start running [`MainCommand`]s sent by the worker. Until a handler is installed,
+    /// commands fail with [`CommandResult::Err`].
+    ///
+    pub fn install_command_handler(&mut self, handler: CommandHandler) {
+        *self.commands.borrow_mut() = Some(handler);
+    }
+}
+
+impl<WM> worker::WorkerSender<WM> {
+    ///
+    /// Send a command to the main thread. The returned future resolves once it has run.
+    /// The command is sent right away, even if the future is never polled.
+    ///
+    pub fn command(
+        &self,
+        command: MainCommand,
+    ) -> impl std::future::Future<Output = CommandResult> {
+        let r = self.request(command);
+        async move {
+            r.await
+                .unwrap_or_else(|_| CommandResult::Err("no response".to_string()))
+        }
+    }
+
+    fn request(&self, command: MainCommand) -> futures::channel::oneshot::Receiver<CommandResult> {
+        let (s, r) = futures::channel::oneshot::channel();
+
+        let id = {
+            let mut p = self.commands.borrow_mut();
+            let id = p.next_id;
+            p.next_id = p.next_id.wrapping_add(1);
+            p.waiting.insert(id, s);
+            id
+        };
+
+        let (data, _) = internal_message(&ToMain::Command(CommandRequest { id, command }), None);
+        utils::get_worker_global_context()
+            .post_message(&data)
+            .unwrap_throw();
+        r
+    }
+
+    ///
+    /// Send a command to the main thread without waiting on the result.
+    ///
+    pub fn send_command(&self, command: MainCommand) {
+        //The response is dropped when it arrives.
+        drop(self.request(command));
+    }
+}
@@ -0,0 +1,241 @@
+//!
+//! Files dropped onto an element or chosen with a file picker, transferred to the worker
+//! without copying.
+//!
+//! Main thread:
+//! ```ignore
+//! let _drop = sender.forward_file_drop(&canvas, |e| Some(MEvent::File(e)));
+//!
+//! //Let the worker open a file picker with MainCommand::PickFiles.
+//! let picker = sender.file_picker(|e| Some(MEvent::File(e)));
+//! recv.install_command_handler(CommandHandler::new(&canvas).with_file_picker(picker));
+//! ```
+//!
+//! Worker:
+//! ```ignore
+//! MEvent::File(e) => {
+//!     if let Some(buffer) = recv.take_file(&e) {
+//!         let bytes = js_sys::Uint8Array::new(&buffer).to_vec();
+//!         load_level(&e.name, &bytes);
+//!     }
+//! }
+//! ```
+//!
+use super::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+///
+/// A file that was sent to the worker. Its contents are taken with
+/// [`worker::WorkerRecv::take_file`].
+///
+/// The contents are transferred next to the message rather than inside it, so they
+/// are not part of a [`record::Recording`]. A replayed `FileEvent` has nothing to take.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileEvent {
+    pub id: u32,
+    pub name: String,
+    ///
+    /// The MIME type as guessed by the browser. Empty if unknown.
+    ///
+    pub mime: String,
+    ///
+    /// The size in bytes.
+    ///
+    pub size: u64,
+    ///
+    /// Milliseconds since the unix epoch.
+    ///
+    pub last_modified: f64,
+}
+
+type FileSend = Rc<RefCell<dyn FnMut(FileEvent, js_sys::ArrayBuffer)>>;
+
+fn file_send<MW: Serialize + 'static>(
+    worker: &Rc<RefCell<web_sys::Worker>>,
+    mut func: impl FnMut(FileEvent) -> Option<MW> + 'static,
+) -> FileSend {
+    let worker = worker.clone();
+    Rc::new(RefCell::new(move |e: FileEvent, buffer| {
+        let id = e.id;
+        if let Some(val) = func(e) {
+            let (data, transfer) = internal_message(&ToWorker::File { id }, Some(&buffer));
+            data.set(1, JsValue::from_serde(&val).unwrap_throw());
+            worker
+                .borrow()
+                .post_message_with_transfer(&data, &transfer)
+                .unwrap_throw();
+        }
+    }))
+}
+
+//Read every file and send each one once it has been read.
+fn send_files(files: &web_sys::FileList, send: &FileSend) {
+    for file in (0..files.length()).filter_map(|i| files.get(i)) {
+        let send = send.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let Ok(buffer) = wasm_bindgen_futures::JsFuture::from(file.array_buffer()).await else {
+                return;
+            };
+            let e = FileEvent {
+                id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                name: file.name(),
+                mime: file.type_(),
+                size: file.size() as u64,
+                last_modified: file.last_modified(),
+            };
+            (send.borrow_mut())(e, buffer.unchecked_into());
+        });
+    }
+}
+
+pub struct MyListenFileDrop {
+    send: FileSend,
+}
+impl gloop::Listen for MyListenFileDrop {
+    fn call(&mut self, event: &web_sys::Event) {
+        let Some(transfer) = event
+            .dyn_ref::<web_sys::DragEvent>()
+            .and_then(|e| e.data_transfer())
+        else {
+            return;
+        };
+        if event.type_() == "dragover" {
+            transfer.set_drop_effect("copy");
+        } else if let Some(files) = transfer.files() {
+            send_files(&files, &self.send);
+        }
+    }
+}
+
+///
+/// Handle returned by [`main::MainSender::forward_file_drop`]. The listeners are removed when dropped.
+///
+pub struct FileDrop {
+    _dragover: EventListenWithOptions<MyListenFileDrop>,
+    _drop: EventListenWithOptions<MyListenFileDrop>,
+}
+
+pub struct MyListenFilePicker {
+    elem: web_sys::HtmlInputElement,
+    send: FileSend,
+}
+impl gloop::Listen for MyListenFilePicker {
+    fn call(&mut self, _: &web_sys::Event) {
+        if let Some(files) = self.elem.files() {
+            send_files(&files, &self.send);
+        }
+        //So the same file can be picked again.
+        self.elem.set_value("");
+    }
+}
+
+struct PickerInner {
+    elem: web_sys::HtmlInputElement,
+    _handle: gloop::EventListen<MyListenFilePicker>,
+}
+impl Drop for PickerInner {
+    fn drop(&mut self) {
+        self.elem.remove();
+    }
+}
+
+///
+/// A file picker created by [`main::MainSender::file_picker`].
+/// Clones refer to the same picker, which is removed once all of them are dropped.
+///
+#[derive(Clone)]
+pub struct FilePicker {
+    inner: Rc<PickerInner>,
+}
+
+impl std::fmt::Debug for FilePicker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FilePicker")
+            .field("elem", &self.inner.elem)
+            .finish()
+    }
+}
+
+impl FilePicker {
+    ///
+    /// Open the picker. `accept` is a list of file types like `"image/*,.json"`, or empty for any.
+    /// Browsers only open it in response to a user gesture.
+    ///
+    pub fn open(&self, accept: &str, multiple: bool) {
+        let elem = &self.inner.elem;
+        elem.set_accept(accept);
+        elem.set_multiple(multiple);
+        elem.click();
+    }
+}
+
+impl<MW: Serialize + 'static> main::MainSender<MW> {
+    ///
+    /// Send files dropped onto the element to the worker as [`FileEvent`]s mapped
+    /// through the function.
+    ///
+    pub fn forward_file_drop<F: FnMut(FileEvent) -> Option<MW> + 'static>(
+        &self,
+        elem: &web_sys::EventTarget,
+        func: F,
+    ) -> FileDrop {
+        let send = file_send(&self.worker, func);
+        let options = EventOptions {
+            prevent_default: true,
+            ..Default::default()
+        };
+        let listen = || MyListenFileDrop { send: send.clone() };
+        FileDrop {
+            _dragover: EventListenWithOptions::new(elem, "dragover", options, listen()),
+            _drop: EventListenWithOptions::new(elem, "drop", options, listen()),
+        }
+    }
+
+    ///
+    /// Create a file picker that sends the chosen files to the worker as [`FileEvent`]s
+    /// mapped through the function.
+    ///
+    pub fn file_picker<F: FnMut(FileEvent) -> Option<MW> + 'static>(&self, func: F) -> FilePicker {
+        let document = gloo::utils::document();
+        let elem: web_sys::HtmlInputElement = document
+            .create_element("input")
+            .unwrap_throw()
+            .dyn_into()
+            .unwrap_throw();
+        elem.set_type("file");
+        elem.set_attribute("style", "display: none;").unwrap_throw();
+        //Some browsers only open pickers that are in the document.
+        document
+            .body()
+            .unwrap_throw()
+            .append_child(&elem)
+            .unwrap_throw();
+
+        let _handle = gloop::EventListen::new(
+            &elem,
+            "change",
+            MyListenFilePicker {
+                elem: elem.clone(),
+                send: file_send(&self.worker, func),
+            },
+        );
+
+        FilePicker {
+            inner: Rc::new(PickerInner { elem, _handle }),
+        }
+    }
+}
+
+impl<MW, T: main::Transferable> worker::WorkerRecv<MW, T> {
+    ///
+    /// Take the contents of a file. Returns `None` if it was already taken, or if the
+    /// event came from a replay since recordings don't store file contents.
+    /// Contents are kept until taken, so every [`FileEvent`] should be handled.
+    ///
+    pub fn take_file(&mut self, file: &FileEvent) -> Option<js_sys::ArrayBuffer> {
+        self.files.borrow_mut().remove(&file.id)
+    }
+}
@@ -0,0 +1,205 @@
+//!
+//! Run the simulation at a fixed tick rate independent of how often frames are rendered.
+//!
+//! ```ignore
+//! let mut timer = shogo::Timer::new(60);
+//! let mut fixed = FixedStep::new(50.0);
+//! loop {
+//!     let info = timer.next().await;
+//!     fixed.frame(
+//!         info.time,
+//!         |dt| world.update(dt),
+//!         |alpha| world.render(alpha),
+//!     );
+//! }
+//! ```
+//!
+//! Each frame, real elapsed time is added to an accumulator and `update` runs once for
+//! every whole tick in it. Whatever is left over is passed to `render` as `alpha`, the
+//! fraction of a tick since the last update, to interpolate between the previous and
+//! current state. Since `update` always advances by the same amount, the simulation
+//! is the same no matter how frames are paced.
+//!
+
+///
+/// Accumulates elapsed time and decides how many fixed ticks to run each frame.
+///
+#[derive(Debug, Clone)]
+pub struct FixedStep {
+    step: f64,
+    max_ticks: u32,
+    accumulator: f64,
+    last: Option<f64>,
+    ticks: u64,
+}
+
+impl FixedStep {
+    ///
+    /// `tick_rate` is the number of ticks per second.
+    ///
+    pub fn new(tick_rate: f64) -> Self {
+        assert!(tick_rate > 0.0);
+        FixedStep {
+            step: 1000.0 / tick_rate,
+            max_ticks: 5,
+            accumulator: 0.0,
+            last: None,
+            ticks: 0,
+        }
+    }
+
+    ///
+    /// The most ticks run in a single frame. If more time than that has built up, e.g.
+    /// because the page was hidden, the rest is dropped so the loop doesn't spiral trying
+    /// to catch up. Defaults to 5.
+    ///
+    pub fn with_max_catch_up(mut self, max_ticks: u32) -> Self {
+        assert!(max_ticks > 0);
+        self.max_ticks = max_ticks;
+        self
+    }
+
+    ///
+    /// The length of a tick in milliseconds.
+    ///
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    ///
+    /// The total number of ticks run.
+    ///
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    ///
+    /// The fraction of a tick that has passed since the last tick, in `[0,1)`.
+    ///
+    pub fn alpha(&self) -> f64 {
+        self.accumulator / self.step
+    }
+
+    ///
+    /// Add the time elapsed since the last call and return how many ticks to run now.
+    /// `now` is in milliseconds. The first call only records the time and returns 0.
+    ///
+    pub fn advance(&mut self, now: f64) -> u32 {
+        let elapsed = match self.last.replace(now) {
+            Some(last) => (now - last).max(0.0),
+            None => 0.0,
+        };
+
+        let max = self.step * self.max_ticks as f64;
+        self.accumulator = (self.accumulator + elapsed).min(max);
+
+        let mut n = 0;
+        while self.accumulator >= self.step && n < self.max_ticks {
+            self.accumulator -= self.step;
+            n += 1;
+        }
+        self.ticks += n as u64;
+        n
+    }
+
+    ///
+    /// Run `update` for each tick that is due, with the tick length in seconds,
+    /// then `render` with the interpolation alpha.
+    ///
+    pub fn frame(&mut self, now: f64, mut update: impl FnMut(f64), render: impl FnOnce(f64)) {
+        let dt = self.step / 1000.0;
+        for _ in 0..self.advance(now) {
+            update(dt);
+        }
+        render(self.alpha());
+    }
+
+    ///
+    /// Forget the accumulated time, e.g. after the loop was paused.
+    /// The next call to [`FixedStep::advance`] starts counting again.
+    ///
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+        self.last = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Run frames at each time and return the total number of ticks.
+    fn run(fixed: &mut FixedStep, times: &[f64]) -> u32 {
+        times.iter().map(|&t| fixed.advance(t)).sum()
+    }
+
+    #[test]
+    fn pacing_does_not_change_ticks() {
+        let mut even = FixedStep::new(50.0);
+        let mut uneven = FixedStep::new(50.0);
+        assert_eq!(even.step(), 20.0);
+
+        assert_eq!(run(&mut even, &[0.0, 20.0, 40.0, 60.0, 80.0, 100.0]), 5);
+        assert_eq!(run(&mut uneven, &[0.0, 7.0, 13.0, 48.0, 61.0, 100.0]), 5);
+        assert_eq!(even.ticks(), uneven.ticks());
+        assert_eq!(even.alpha(), uneven.alpha());
+    }
+
+    #[test]
+    fn first_call_only_records_time() {
+        let mut fixed = FixedStep::new(50.0);
+        assert_eq!(fixed.advance(1000.0), 0);
+        assert_eq!(fixed.alpha(), 0.0);
+        assert_eq!(fixed.advance(1030.0), 1);
+        assert_eq!(fixed.alpha(), 0.5);
+    }
+
+    #[test]
+    fn catch_up_is_clamped() {
+        let mut fixed = FixedStep::new(50.0).with_max_catch_up(3);
+        fixed.advance(0.0);
+        //A second in the background would be 50 ticks.
+        assert_eq!(fixed.advance(1000.0), 3);
+        assert_eq!(fixed.alpha(), 0.0);
+        //The rest was dropped rather than run later.
+        assert_eq!(fixed.advance(1010.0), 0);
+        assert_eq!(fixed.advance(1020.0), 1);
+        assert_eq!(fixed.ticks(), 4);
+    }
+
+    #[test]
+    fn alpha_stays_below_one() {
+        let mut fixed = FixedStep::new(60.0);
+        let mut t = 0.0;
+        for i in 0..1000 {
+            t += [3.0, 16.7, 17.0, 33.4, 250.0][i % 5];
+            fixed.advance(t);
+            let alpha = fixed.alpha();
+            assert!((0.0..1.0).contains(&alpha), "{}", alpha);
+        }
+    }
+
+    #[test]
+    fn frame_passes_seconds_and_alpha() {
+        let mut fixed = FixedStep::new(50.0);
+        fixed.advance(0.0);
+        let mut dts = vec![];
+        let mut alpha = None;
+        fixed.frame(50.0, |dt| dts.push(dt), |a| alpha = Some(a));
+        assert_eq!(dts, [0.02, 0.02]);
+        assert_eq!(alpha, Some(0.5));
+    }
+
+    #[test]
+    fn reset() {
+        let mut fixed = FixedStep::new(50.0);
+        fixed.advance(0.0);
+        fixed.advance(30.0);
+        fixed.reset();
+        assert_eq!(fixed.alpha(), 0.0);
+        //Time spent paused isn't counted.
+        assert_eq!(fixed.advance(5000.0), 0);
+        assert_eq!(fixed.advance(5020.0), 1);
+        assert_eq!(fixed.ticks(), 2);
+    }
+}
@@ -0,0 +1,499 @@
+//!
+//! Sources of frames for the worker loop.
+//!
+//! ```ignore
+//! //requestAnimationFrame where the worker supports it, otherwise a 60fps Timer.
+//! let mut frames = AutoFrameSource::new(60);
+//! loop {
+//!     let info = frames.next_frame().await;
+//!     player.x += speed * info.delta_secs();
+//!     //...
+//! }
+//! ```
+//!
+use super::*;
+use futures::channel::oneshot;
+
+///
+/// Timing of a frame. Times are in milliseconds.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct FrameInfo {
+    ///
+    /// Counts up from 0 with every frame.
+    ///
+    pub index: u64,
+    ///
+    /// When the frame started.
+    ///
+    pub time: f64,
+    ///
+    /// Time since the previous frame started. 0 for the first frame.
+    ///
+    pub delta: f64,
+    ///
+    /// How long after it was due the frame started.
+    ///
+    pub lateness: f64,
+    ///
+    /// The number of frames that were due but skipped since the previous frame.
+    ///
+    pub skipped: u32,
+}
+impl FrameInfo {
+    pub fn delta_secs(&self) -> f32 {
+        (self.delta / 1000.0) as f32
+    }
+}
+
+///
+/// A stream of messages that can be batched by [`FrameTimer`].
+///
+pub trait FrameStream: futures::Stream + Unpin {
+    ///
+    /// Called when a frame starts, before the messages queued so far are collected.
+    ///
+    fn start_frame(&mut self, _info: &FrameInfo) {}
+}
+
+impl<T> FrameStream for futures::channel::mpsc::UnboundedReceiver<T> {}
+
+impl<S: FrameStream + ?Sized> FrameStream for &mut S {
+    fn start_frame(&mut self, info: &FrameInfo) {
+        (**self).start_frame(info)
+    }
+}
+
+impl<MW, T: main::Transferable> futures::Stream for worker::WorkerRecv<MW, T> {
+    type Item = MW;
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<MW>> {
+        std::pin::Pin::new(self.recv()).poll_next(cx)
+    }
+}
+
+///
+/// Sets the frame used for recording and replay to the frame index.
+///
+impl<MW, T: main::Transferable> FrameStream for worker::WorkerRecv<MW, T> {
+    fn start_frame(&mut self, info: &FrameInfo) {
+        self.set_frame(info.index);
+    }
+}
+
+///
+/// Something the worker loop can wait on for the next frame.
+///
+pub trait FrameSource {
+    ///
+    /// Wait until the next frame should start.
+    ///
+    fn next_frame(&mut self) -> impl std::future::Future<Output = FrameInfo>;
+
+    ///
+    /// Called by [`FrameTimer`] when messages arrived since the last frame.
+    /// Only messages that pass [`FrameTimer::with_input_filter`] count.
+    ///
+    fn message_arrived(&mut self) {}
+
+    ///
+    /// If true, [`FrameTimer`] stops waiting when an input message arrives, calls
+    /// [`FrameSource::message_arrived`] and waits again, so the source can
+    /// bring the frame forward.
+    ///
+    fn restart_on_message(&self) -> bool {
+        false
+    }
+}
+
+impl<C: clock::Clock> FrameSource for Timer<C> {
+    fn next_frame(&mut self) -> impl std::future::Future<Output = FrameInfo> {
+        self.next()
+    }
+}
+
+///
+/// Frames from `requestAnimationFrame` in a worker, synchronized with the display refresh.
+/// Browsers stop firing them while the page is hidden.
+///
+pub struct AnimationFrame {
+    scope: web_sys::DedicatedWorkerGlobalScope,
+    send: Rc<RefCell<Option<oneshot::Sender<f64>>>>,
+    callback: Closure<dyn FnMut(f64)>,
+    handle: Option<i32>,
+    last: Option<f64>,
+    index: u64,
+    //Estimate of the display refresh interval, to tell when frames were skipped.
+    interval: f64,
+}
+
+impl AnimationFrame {
+    ///
+    /// Returns `None` if not in a worker or the worker doesn't support `requestAnimationFrame`.
+    ///
+    pub fn new() -> Option<Self> {
+        let scope: web_sys::DedicatedWorkerGlobalScope = js_sys::global().dyn_into().ok()?;
+        let supported = js_sys::Reflect::has(&scope, &JsValue::from_str("requestAnimationFrame"))
+            .unwrap_or(false);
+        if !supported {
+            return None;
+        }
+
+        let send: Rc<RefCell<Option<oneshot::Sender<f64>>>> = Rc::new(RefCell::new(None));
+        let s = send.clone();
+        let callback = Closure::<dyn FnMut(f64)>::new(move |time: f64| {
+            if let Some(s) = s.borrow_mut().take() {
+                let _ = s.send(time);
+            }
+        });
+
+        Some(AnimationFrame {
+            scope,
+            send,
+            callback,
+            handle: None,
+            last: None,
+            index: 0,
+            interval: 1000.0 / 60.0,
+        })
+    }
+
+    ///
+    /// Wait for the next animation frame. Its time is the timestamp passed to the callback.
+    ///
+    pub async fn next(&mut self) -> FrameInfo {
+        //A previous wait may have been dropped before its frame arrived.
+        if let Some(h) = self.handle.take() {
+            let _ = self.scope.cancel_animation_frame(h);
+        }
+
+        let (s, r) = oneshot::channel();
+        *self.send.borrow_mut() = Some(s);
+        self.handle = Some(
+            self.scope
+                .request_animation_frame(self.callback.as_ref().unchecked_ref())
+                .unwrap_throw(),
+        );
+
+        let time = r.await.unwrap_throw();
+        self.handle = None;
+
+        let lateness = match self.scope.performance() {
+            Some(p) => (p.now() - time).max(0.0),
+            None => 0.0,
+        };
+
+        let delta = self.last.map(|l| time - l).unwrap_or(0.0);
+        //Only frames that weren't skipped refine the estimate.
+        if delta > 0.0 && delta < self.interval * 1.5 {
+            self.interval = self.interval * 0.9 + delta * 0.1;
+        }
+        let skipped = ((delta / self.interval).round() as u32).saturating_sub(1);
+
+        let info = FrameInfo {
+            index: self.index,
+            time,
+            delta,
+            lateness,
+            skipped,
+        };
+        self.last = Some(time);
+        self.index += 1;
+        info
+    }
+}
+
+impl Drop for AnimationFrame {
+    fn drop(&mut self) {
+        if let Some(h) = self.handle.take() {
+            let _ = self.scope.cancel_animation_frame(h);
+        }
+    }
+}
+
+impl FrameSource for AnimationFrame {
+    fn next_frame(&mut self) -> impl std::future::Future<Output = FrameInfo> {
+        self.next()
+    }
+}
+
+///
+/// [`AnimationFrame`] where supported, otherwise a [`Timer`].
+///
+pub enum AutoFrameSource {
+    AnimationFrame(AnimationFrame),
+    Timer(Timer),
+}
+
+impl AutoFrameSource {
+    ///
+    /// `frame_rate` is only used by the [`Timer`] fallback.
+    ///
+    pub fn new(frame_rate: usize) -> Self {
+        match AnimationFrame::new() {
+            Some(a) => AutoFrameSource::AnimationFrame(a),
+            None => AutoFrameSource::Timer(Timer::new(frame_rate)),
+        }
+    }
+
+    pub fn is_animation_frame(&self) -> bool {
+        matches!(self, AutoFrameSource::AnimationFrame(_))
+    }
+}
+
+impl FrameSource for AutoFrameSource {
+    async fn next_frame(&mut self) -> FrameInfo {
+        match self {
+            AutoFrameSource::AnimationFrame(a) => a.next_frame().await,
+            AutoFrameSource::Timer(t) => t.next_frame().await,
+        }
+    }
+}
+
+///
+/// A [`Timer`] that drops to a low frame rate when no input has arrived for a while
+/// or the page is hidden, and snaps back to the normal rate on the next input message.
+/// Used with [`FrameTimer`], which tells it when messages arrive. Use
+/// [`FrameTimer::with_input_filter`] so only input counts, not e.g. resizes or lifecycle changes.
+///
+/// ```ignore
+/// let mut frame_timer = FrameTimer::with_source(IdleTimer::new(60, 2, 3000.0), recv)
+///     .with_input_filter(|e| matches!(e, MEvent::Input(_)));
+/// loop {
+///     let frame = frame_timer.next().await;
+///     for e in frame.events() {
+///         if let MEvent::Lifecycle(l) = e {
+///             lifecycle.handle(l);
+///         }
+///     }
+///     frame_timer.source().set_hidden(!lifecycle.visible);
+///     //...
+/// }
+/// ```
+///
+pub struct IdleTimer<C = clock::WorkerClock> {
+    timer: Timer<C>,
+    active_rate: usize,
+    idle_rate: usize,
+    idle_after: f64,
+    last_input: f64,
+    hidden: bool,
+}
+
+impl IdleTimer {
+    ///
+    /// Run at `active_rate`, dropping to `idle_rate` after `idle_after` milliseconds
+    /// without input messages. Frame rates must be greater than 0. Call from within a webworker.
+    ///
+    pub fn new(active_rate: usize, idle_rate: usize, idle_after: f64) -> Self {
+        Self::with_clock(
+            active_rate,
+            idle_rate,
+            idle_after,
+            clock::WorkerClock::new(),
+        )
+    }
+}
+
+impl<C: clock::Clock> IdleTimer<C> {
+    pub fn with_clock(active_rate: usize, idle_rate: usize, idle_after: f64, clock: C) -> Self {
+        assert!(active_rate > 0);
+        assert!(idle_rate > 0);
+        let last_input = clock.now();
+        IdleTimer {
+            timer: Timer::with_clock(active_rate, clock),
+            active_rate,
+            idle_rate,
+            idle_after,
+            last_input,
+            hidden: false,
+        }
+    }
+
+    pub fn timer(&self) -> &Timer<C> {
+        &self.timer
+    }
+
+    pub fn set_active_rate(&mut self, frame_rate: usize) {
+        assert!(frame_rate > 0);
+        self.active_rate = frame_rate;
+    }
+
+    pub fn set_idle_rate(&mut self, frame_rate: usize) {
+        assert!(frame_rate > 0);
+        self.idle_rate = frame_rate;
+    }
+
+    pub fn set_idle_after(&mut self, idle_after: f64) {
+        self.idle_after = idle_after;
+    }
+
+    ///
+    /// While hidden the idle rate is used, and messages don't snap back to the normal rate.
+    ///
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
+
+    ///
+    /// Count as input, e.g. while an animation is playing that should run at the normal rate.
+    ///
+    pub fn input(&mut self) {
+        self.last_input = self.timer.clock().now();
+    }
+
+    ///
+    /// True if running at the idle rate because there was no input for a while.
+    ///
+    pub fn is_idle(&self) -> bool {
+        self.timer.clock().now() - self.last_input > self.idle_after
+    }
+
+    ///
+    /// The frame rate the next frame will be timed with.
+    ///
+    pub fn frame_rate(&self) -> usize {
+        if self.hidden || self.is_idle() {
+            self.idle_rate
+        } else {
+            self.active_rate
+        }
+    }
+}
+
+impl<C: clock::Clock> FrameSource for IdleTimer<C> {
+    fn next_frame(&mut self) -> impl std::future::Future<Output = FrameInfo> {
+        let rate = self.frame_rate();
+        self.timer.set_frame_rate(rate);
+        self.timer.next()
+    }
+
+    fn message_arrived(&mut self) {
+        self.input();
+    }
+
+    fn restart_on_message(&self) -> bool {
+        !self.hidden && self.is_idle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::{Clock, MockClock};
+    use futures::channel::mpsc;
+    use futures::executor::block_on;
+    use std::future::Future;
+    use std::task::{Context, Poll};
+
+    //Like MockClock, except sleeping waits for the time to be advanced,
+    //so a frame can be pending while messages arrive.
+    #[derive(Clone, Default)]
+    struct StepClock(MockClock);
+    impl Clock for StepClock {
+        fn now(&self) -> f64 {
+            self.0.now()
+        }
+        fn sleep(&self, ms: f64) -> impl Future<Output = ()> {
+            let clock = self.0.clone();
+            let until = clock.now() + ms;
+            futures::future::poll_fn(move |_| {
+                if clock.now() >= until {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            })
+        }
+    }
+
+    fn poll<F: Future>(f: std::pin::Pin<&mut F>) -> Poll<F::Output> {
+        f.poll(&mut Context::from_waker(futures::task::noop_waker_ref()))
+    }
+
+    #[test]
+    fn goes_idle() {
+        let clock = MockClock::new();
+        let mut idle = IdleTimer::with_clock(60, 2, 1000.0, clock.clone());
+        assert_eq!(idle.frame_rate(), 60);
+
+        clock.advance(1000.0);
+        assert!(!idle.is_idle());
+        clock.advance(1.0);
+        assert!(idle.is_idle());
+        assert_eq!(idle.frame_rate(), 2);
+        assert!(idle.restart_on_message());
+
+        idle.input();
+        assert!(!idle.is_idle());
+        assert_eq!(idle.frame_rate(), 60);
+    }
+
+    #[test]
+    fn idle_frames_are_slower() {
+        let clock = MockClock::new();
+        let mut idle = IdleTimer::with_clock(60, 2, 1000.0, clock.clone());
+        block_on(idle.next_frame());
+        clock.advance(2000.0);
+        block_on(idle.next_frame());
+        let info = block_on(idle.next_frame());
+        assert_eq!(info.delta, 500.0);
+    }
+
+    #[test]
+    fn hidden() {
+        let clock = MockClock::new();
+        let mut idle = IdleTimer::with_clock(60, 2, 1000.0, clock.clone());
+        idle.set_hidden(true);
+        assert_eq!(idle.frame_rate(), 2);
+        assert!(!idle.is_idle());
+
+        //Messages don't snap back while hidden.
+        clock.advance(2000.0);
+        assert!(!idle.restart_on_message());
+
+        idle.set_hidden(false);
+        assert!(idle.restart_on_message());
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_rate() {
+        IdleTimer::with_clock(60, 2, 1000.0, MockClock::new()).set_idle_rate(0);
+    }
+
+    #[test]
+    fn input_snaps_back() {
+        let clock = StepClock::default();
+        let (send, recv) = mpsc::unbounded::<u32>();
+        let mut timer =
+            FrameTimer::with_source(IdleTimer::with_clock(60, 2, 1000.0, clock.clone()), recv)
+                .with_input_filter(|e| *e != 0);
+
+        assert_eq!(block_on(timer.next()).info().time, 0.0);
+        clock.0.advance(2000.0);
+        assert_eq!(block_on(timer.next()).info().time, 2000.0);
+
+        //The next idle frame is due at 2500.
+        let mut next = Box::pin(timer.next());
+        assert!(poll(next.as_mut()).is_pending());
+
+        //Messages that aren't input don't bring it forward.
+        send.unbounded_send(0).unwrap();
+        assert!(poll(next.as_mut()).is_pending());
+        clock.0.advance(20.0);
+        assert!(poll(next.as_mut()).is_pending());
+
+        //Input does.
+        send.unbounded_send(1).unwrap();
+        let Poll::Ready(frame) = poll(next.as_mut()) else {
+            panic!("frame should be due at the active rate");
+        };
+        assert_eq!(frame.info().time, 2020.0);
+        assert_eq!(frame.events(), &[0, 1]);
+        drop(next);
+        assert!(!timer.source().is_idle());
+    }
+}
@@ -0,0 +1,447 @@
+//!
+//! Gamepad support. The Gamepad API is only available on the main thread, so gamepads
+//! are polled there every animation frame and changes are forwarded to the worker.
+//!
+//! ```ignore
+//! let _poller = sender.poll_gamepads(0.1, |e| Some(MEvent::Gamepad(e)));
+//! ```
+//!
+use super::*;
+
+///
+/// A change in gamepad state.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum GamepadEvent {
+    Connected {
+        index: u32,
+        id: String,
+        buttons: u32,
+        axes: u32,
+    },
+    Disconnected {
+        index: u32,
+    },
+    ///
+    /// Sent when `pressed` changes or `value` moves by at least [`BUTTON_STEP`]
+    /// since it was last sent. The value has had the deadzone applied.
+    ///
+    Button {
+        index: u32,
+        button: u32,
+        pressed: bool,
+        value: f32,
+    },
+    ///
+    /// The value has had the deadzone applied and is in the range `[-1,1]`.
+    ///
+    Axis {
+        index: u32,
+        axis: u32,
+        value: f32,
+    },
+}
+
+///
+/// The smallest change in an analog button value that is reported, so that noise
+/// from a held trigger doesn't produce an event every frame.
+///
+pub const BUTTON_STEP: f32 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ButtonState {
+    pub pressed: bool,
+    pub value: f32,
+}
+
+///
+/// The state of one gamepad at a point in time.
+///
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GamepadSnapshot {
+    pub id: String,
+    pub buttons: Vec<ButtonState>,
+    pub axes: Vec<f32>,
+}
+impl GamepadSnapshot {
+    pub fn from_gamepad(pad: &web_sys::Gamepad) -> GamepadSnapshot {
+        let buttons = pad
+            .buttons()
+            .iter()
+            .map(|b| {
+                let b: web_sys::GamepadButton = b.unchecked_into();
+                ButtonState {
+                    pressed: b.pressed(),
+                    value: b.value() as f32,
+                }
+            })
+            .collect();
+
+        let axes = pad
+            .axes()
+            .iter()
+            .map(|a| a.as_f64().unwrap_or(0.0) as f32)
+            .collect();
+
+        GamepadSnapshot {
+            id: pad.id(),
+            buttons,
+            axes,
+        }
+    }
+}
+
+///
+/// Remembers the last state of every gamepad and produces events for what changed.
+///
+#[derive(Debug, Clone, Default)]
+pub struct GamepadTracker {
+    pads: Vec<Option<GamepadSnapshot>>,
+    deadzone: f32,
+}
+
+impl GamepadTracker {
+    ///
+    /// Axis values with a magnitude below `deadzone` are reported as zero.
+    /// Values outside it are rescaled so the axis still covers `[-1,1]`.
+    /// The same applies to analog button values.
+    ///
+    /// # Panics
+    ///
+    /// If `deadzone` is not in `[0,1)`.
+    ///
+    pub fn new(deadzone: f32) -> Self {
+        assert!((0.0..1.0).contains(&deadzone));
+        GamepadTracker {
+            pads: vec![],
+            deadzone,
+        }
+    }
+
+    pub fn apply_deadzone(&self, value: f32) -> f32 {
+        let mag = value.abs();
+        if mag < self.deadzone {
+            0.0
+        } else {
+            value.signum() * ((mag - self.deadzone) / (1.0 - self.deadzone)).min(1.0)
+        }
+    }
+
+    ///
+    /// Update the state of the gamepad at `index`. `None` means no gamepad is connected there.
+    ///
+    pub fn update(
+        &mut self,
+        index: u32,
+        pad: Option<GamepadSnapshot>,
+        out: &mut Vec<GamepadEvent>,
+    ) {
+        let mut pad = pad.map(|mut p| {
+            for a in p.axes.iter_mut() {
+                *a = self.apply_deadzone(*a);
+            }
+            for b in p.buttons.iter_mut() {
+                b.value = self.apply_deadzone(b.value);
+            }
+            p
+        });
+
+        let i = index as usize;
+        if self.pads.len() <= i {
+            self.pads.resize(i + 1, None);
+        }
+
+        match (self.pads[i].as_ref(), pad.as_mut()) {
+            (None, None) => {}
+            (Some(_), None) => out.push(GamepadEvent::Disconnected { index }),
+            (old, Some(new)) => {
+                let old = match old {
+                    Some(old) if old.id == new.id => old,
+                    _ => {
+                        if old.is_some() {
+                            out.push(GamepadEvent::Disconnected { index });
+                        }
+                        out.push(GamepadEvent::Connected {
+                            index,
+                            id: new.id.clone(),
+                            buttons: new.buttons.len() as u32,
+                            axes: new.axes.len() as u32,
+                        });
+                        &GamepadSnapshot::default()
+                    }
+                };
+
+                for (button, b) in new.buttons.iter_mut().enumerate() {
+                    let last = old.buttons.get(button).copied().unwrap_or_default();
+                    let moved = (b.value - last.value).abs() >= BUTTON_STEP
+                        || (b.value != last.value && (b.value == 0.0 || b.value == 1.0));
+                    if b.pressed != last.pressed || moved {
+                        out.push(GamepadEvent::Button {
+                            index,
+                            button: button as u32,
+                            pressed: b.pressed,
+                            value: b.value,
+                        });
+                    } else {
+                        //Remember the value that was sent so slow drift is still
+                        //reported once it adds up.
+                        b.value = last.value;
+                    }
+                }
+
+                for (axis, &value) in new.axes.iter().enumerate() {
+                    if old.axes.get(axis).copied().unwrap_or(0.0) != value {
+                        out.push(GamepadEvent::Axis {
+                            index,
+                            axis: axis as u32,
+                            value,
+                        });
+                    }
+                }
+            }
+        }
+
+        self.pads[i] = pad;
+    }
+}
+
+///
+/// Handle returned by [`main::MainSender::poll_gamepads`]. Polling stops when dropped.
+///
+pub struct GamepadPoller {
+    state: Rc<RefCell<PollState>>,
+}
+impl Drop for GamepadPoller {
+    fn drop(&mut self) {
+        if let Some(id) = self.state.borrow_mut().frame_id.take() {
+            let _ = gloo::utils::window().cancel_animation_frame(id);
+        }
+    }
+}
+
+struct PollState {
+    frame_id: Option<i32>,
+    closure: Option<Closure<dyn FnMut(f64)>>,
+    poll: Box<dyn FnMut()>,
+}
+
+impl<MW: Serialize + 'static> main::MainSender<MW> {
+    ///
+    /// Poll `navigator.getGamepads()` every animation frame and forward every change
+    /// mapped through the function. See [`GamepadTracker::new`] for what `deadzone` does.
+    ///
+    pub fn poll_gamepads<F: FnMut(GamepadEvent) -> Option<MW> + 'static>(
+        &self,
+        deadzone: f32,
+        mut func: F,
+    ) -> GamepadPoller {
+        let worker = self.worker.clone();
+        let mut tracker = GamepadTracker::new(deadzone);
+        let mut events = vec![];
+
+        let poll = move || {
+            let pads = gloo::utils::window()
+                .navigator()
+                .get_gamepads()
+                .unwrap_or_default();
+
+            //Also visit slots we know about in case the list shrunk.
+            let len = pads.length().max(tracker.pads.len() as u32);
+            for index in 0..len {
+                let pad = pads
+                    .get(index)
+                    .dyn_into::<web_sys::Gamepad>()
+                    .ok()
+                    .filter(|p| p.connected())
+                    .map(|p| GamepadSnapshot::from_gamepad(&p));
+                tracker.update(index, pad, &mut events);
+            }
+
+            for e in events.drain(..) {
+                if let Some(val) = func(e) {
+                    main::post_to_worker(&worker.borrow(), &val);
+                }
+            }
+        };
+
+        let state = Rc::new(RefCell::new(PollState {
+            frame_id: None,
+            closure: None,
+            poll: Box::new(poll),
+        }));
+
+        let weak = Rc::downgrade(&state);
+        let closure = Closure::<dyn FnMut(f64)>::new(move |_: f64| {
+            if let Some(state) = weak.upgrade() {
+                let mut state = state.borrow_mut();
+                (state.poll)();
+                let closure = state.closure.as_ref().unwrap_throw();
+                let id = gloo::utils::window()
+                    .request_animation_frame(closure.as_ref().unchecked_ref())
+                    .unwrap_throw();
+                state.frame_id = Some(id);
+            }
+        });
+
+        let id = gloo::utils::window()
+            .request_animation_frame(closure.as_ref().unchecked_ref())
+            .unwrap_throw();
+
+        {
+            let mut s = state.borrow_mut();
+            s.frame_id = Some(id);
+            s.closure = Some(closure);
+        }
+
+        GamepadPoller { state }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pad(id: &str, buttons: &[f32], axes: &[f32]) -> Option<GamepadSnapshot> {
+        Some(GamepadSnapshot {
+            id: id.to_owned(),
+            buttons: buttons
+                .iter()
+                .map(|&value| ButtonState {
+                    pressed: value > 0.5,
+                    value,
+                })
+                .collect(),
+            axes: axes.to_vec(),
+        })
+    }
+
+    fn update(t: &mut GamepadTracker, index: u32, p: Option<GamepadSnapshot>) -> Vec<GamepadEvent> {
+        let mut out = vec![];
+        t.update(index, p, &mut out);
+        out
+    }
+
+    fn button(button: u32, pressed: bool, value: f32) -> GamepadEvent {
+        GamepadEvent::Button {
+            index: 0,
+            button,
+            pressed,
+            value,
+        }
+    }
+
+    #[test]
+    fn connect_and_disconnect() {
+        let mut t = GamepadTracker::new(0.1);
+        assert_eq!(update(&mut t, 0, None), []);
+        assert_eq!(
+            update(&mut t, 2, pad("a", &[0.0, 0.0], &[0.0])),
+            [GamepadEvent::Connected {
+                index: 2,
+                id: "a".to_owned(),
+                buttons: 2,
+                axes: 1,
+            }]
+        );
+        assert_eq!(update(&mut t, 2, pad("a", &[0.0, 0.0], &[0.0])), []);
+
+        //A different pad in the same slot.
+        let events = update(&mut t, 2, pad("b", &[], &[]));
+        assert_eq!(events[0], GamepadEvent::Disconnected { index: 2 });
+        assert!(matches!(
+            events[1],
+            GamepadEvent::Connected { index: 2, .. }
+        ));
+
+        assert_eq!(
+            update(&mut t, 2, None),
+            [GamepadEvent::Disconnected { index: 2 }]
+        );
+        assert_eq!(update(&mut t, 2, None), []);
+    }
+
+    #[test]
+    fn connecting_reports_held_buttons() {
+        let mut t = GamepadTracker::new(0.0);
+        let events = update(&mut t, 0, pad("a", &[0.0, 1.0], &[]));
+        assert_eq!(events[1..], [button(1, true, 1.0)]);
+    }
+
+    #[test]
+    fn button_edges() {
+        let mut t = GamepadTracker::new(0.0);
+        update(&mut t, 0, pad("a", &[0.0, 0.0], &[]));
+        assert_eq!(
+            update(&mut t, 0, pad("a", &[1.0, 0.0], &[])),
+            [button(0, true, 1.0)]
+        );
+        assert_eq!(update(&mut t, 0, pad("a", &[1.0, 0.0], &[])), []);
+        assert_eq!(
+            update(&mut t, 0, pad("a", &[0.0, 0.0], &[])),
+            [button(0, false, 0.0)]
+        );
+    }
+
+    #[test]
+    fn analog_button_noise() {
+        let mut t = GamepadTracker::new(0.0);
+        update(&mut t, 0, pad("a", &[0.0], &[]));
+        assert_eq!(
+            update(&mut t, 0, pad("a", &[0.4], &[])),
+            [button(0, false, 0.4)]
+        );
+        //Jitter below the step is ignored...
+        assert_eq!(update(&mut t, 0, pad("a", &[0.404], &[])), []);
+        assert_eq!(update(&mut t, 0, pad("a", &[0.397], &[])), []);
+        //...but drift is reported once it adds up.
+        assert_eq!(update(&mut t, 0, pad("a", &[0.406], &[])), []);
+        assert_eq!(
+            update(&mut t, 0, pad("a", &[0.412], &[])),
+            [button(0, false, 0.412)]
+        );
+        //Fully released is always reported.
+        update(&mut t, 0, pad("a", &[0.005], &[]));
+        assert_eq!(
+            update(&mut t, 0, pad("a", &[0.0], &[])),
+            [button(0, false, 0.0)]
+        );
+    }
+
+    #[test]
+    fn button_deadzone() {
+        let mut t = GamepadTracker::new(0.1);
+        update(&mut t, 0, pad("a", &[0.0], &[]));
+        assert_eq!(update(&mut t, 0, pad("a", &[0.05], &[])), []);
+        assert_eq!(
+            update(&mut t, 0, pad("a", &[1.0], &[])),
+            [button(0, true, 1.0)]
+        );
+    }
+
+    #[test]
+    fn axis_deadzone() {
+        let mut t = GamepadTracker::new(0.5);
+        assert_eq!(t.apply_deadzone(0.25), 0.0);
+        assert_eq!(t.apply_deadzone(-0.5), -0.0);
+        assert_eq!(t.apply_deadzone(0.75), 0.5);
+        assert_eq!(t.apply_deadzone(-1.0), -1.0);
+        assert_eq!(t.apply_deadzone(1.5), 1.0);
+
+        update(&mut t, 0, pad("a", &[], &[0.0, 0.0]));
+        assert_eq!(update(&mut t, 0, pad("a", &[], &[0.3, -0.2])), []);
+        assert_eq!(
+            update(&mut t, 0, pad("a", &[], &[0.3, -0.75])),
+            [GamepadEvent::Axis {
+                index: 0,
+                axis: 1,
+                value: -0.5,
+            }]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn deadzone_out_of_range() {
+        GamepadTracker::new(1.0);
+    }
+}
@@ -0,0 +1,634 @@
+//!
+//! Recognize taps, long presses, pans, pinches and rotations from forwarded touch or pointer events.
+//!
+//! ```ignore
+//! let mut gestures = GestureRecognizer::new(GestureConfig::default());
+//! let mut out = vec![];
+//! loop {
+//!     let now = timer.now();
+//!     for e in events.drain(..) {
+//!         if let MEvent::Input(e) = e {
+//!             gestures.handle(&e, now, &mut out);
+//!         }
+//!     }
+//!     gestures.update(now, &mut out);
+//!     for g in out.drain(..) {
+//!         //...
+//!     }
+//! }
+//! ```
+//!
+use crate::input::InputEvent;
+use serde::{Deserialize, Serialize};
+
+///
+/// Thresholds used to tell gestures apart. Times are in milliseconds, distances
+/// in whatever coordinates the events are in.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct GestureConfig {
+    ///
+    /// How far a finger can move and still count as a tap or long press.
+    ///
+    pub tap_slop: f32,
+    pub tap_max_duration: f64,
+    pub double_tap_max_interval: f64,
+    pub double_tap_max_distance: f32,
+    pub long_press_duration: f64,
+    ///
+    /// How much the distance between two fingers has to change, as a ratio, before a pinch starts.
+    ///
+    pub pinch_threshold: f32,
+    ///
+    /// How much two fingers have to rotate, in radians, before a rotation starts.
+    ///
+    pub rotate_threshold: f32,
+}
+impl Default for GestureConfig {
+    fn default() -> Self {
+        GestureConfig {
+            tap_slop: 10.0,
+            tap_max_duration: 250.0,
+            double_tap_max_interval: 300.0,
+            double_tap_max_distance: 30.0,
+            long_press_duration: 500.0,
+            pinch_threshold: 0.05,
+            rotate_threshold: 0.1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Start,
+    Move,
+    End,
+}
+
+///
+/// A recognized gesture.
+///
+/// A double tap is preceded by the tap of its first touch.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    Tap {
+        pos: [f32; 2],
+    },
+    DoubleTap {
+        pos: [f32; 2],
+    },
+    LongPress {
+        pos: [f32; 2],
+    },
+    ///
+    /// One finger dragging. `delta` is the movement since the last pan event.
+    ///
+    Pan {
+        phase: Phase,
+        pos: [f32; 2],
+        delta: [f32; 2],
+    },
+    ///
+    /// Two fingers moving apart or together. `scale` is relative to when the fingers went down.
+    ///
+    Pinch {
+        phase: Phase,
+        center: [f32; 2],
+        scale: f32,
+    },
+    ///
+    /// Two fingers rotating. `angle` is in radians relative to when the fingers went down.
+    ///
+    Rotate {
+        phase: Phase,
+        center: [f32; 2],
+        angle: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Track {
+    id: i32,
+    start: [f32; 2],
+    start_time: f64,
+    pos: [f32; 2],
+    moved: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Two {
+    dist: f32,
+    angle: f32,
+    pinching: bool,
+    rotating: bool,
+}
+
+fn sub(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn len(a: [f32; 2]) -> f32 {
+    (a[0] * a[0] + a[1] * a[1]).sqrt()
+}
+
+fn wrap_angle(a: f32) -> f32 {
+    use std::f32::consts::PI;
+    (a + PI).rem_euclid(2.0 * PI) - PI
+}
+
+///
+/// Turns a stream of touch or pointer events into [`Gesture`]s.
+/// Only pointers that went down while the recognizer was watching are tracked,
+/// so mouse movement without a button held is ignored.
+///
+#[derive(Debug, Clone)]
+pub struct GestureRecognizer {
+    config: GestureConfig,
+    tracks: Vec<Track>,
+    //Set once more than one finger was down, so lifting the rest doesn't tap.
+    multi: bool,
+    long_pressed: bool,
+    panning: bool,
+    two: Option<Two>,
+    last_tap: Option<(f64, [f32; 2])>,
+}
+
+impl GestureRecognizer {
+    pub fn new(config: GestureConfig) -> Self {
+        GestureRecognizer {
+            config,
+            tracks: vec![],
+            multi: false,
+            long_pressed: false,
+            panning: false,
+            two: None,
+            last_tap: None,
+        }
+    }
+
+    pub fn config(&self) -> &GestureConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: GestureConfig) {
+        self.config = config;
+    }
+
+    ///
+    /// Feed an event that happened at `time`. Events other than touch and pointer events are ignored.
+    ///
+    pub fn handle(&mut self, event: &InputEvent, time: f64, out: &mut Vec<Gesture>) {
+        self.update(time, out);
+
+        match event {
+            InputEvent::TouchStart { touches } => {
+                for t in touches {
+                    self.down(t.id, t.pos, time, out);
+                }
+            }
+            InputEvent::TouchMove { touches } => {
+                for t in touches {
+                    self.moved(t.id, t.pos, out);
+                }
+            }
+            InputEvent::TouchEnd { touches } => {
+                for t in touches {
+                    self.up(t.id, t.pos, time, false, out);
+                }
+            }
+            InputEvent::TouchCancel { touches } => {
+                for t in touches {
+                    self.up(t.id, t.pos, time, true, out);
+                }
+            }
+            InputEvent::PointerDown { pos, pointer, .. } => self.down(pointer.id, *pos, time, out),
+            InputEvent::PointerMove { pos, pointer, .. } => self.moved(pointer.id, *pos, out),
+            InputEvent::PointerUp { pos, pointer, .. } => {
+                self.up(pointer.id, *pos, time, false, out)
+            }
+            InputEvent::PointerCancel { pos, pointer } => {
+                self.up(pointer.id, *pos, time, true, out)
+            }
+            _ => {}
+        }
+    }
+
+    ///
+    /// Advance time without an event. Call every frame so long presses fire while the finger is still.
+    ///
+    pub fn update(&mut self, time: f64, out: &mut Vec<Gesture>) {
+        if let [t] = self.tracks[..] {
+            if !t.moved
+                && !self.multi
+                && !self.long_pressed
+                && time - t.start_time >= self.config.long_press_duration
+            {
+                self.long_pressed = true;
+                out.push(Gesture::LongPress { pos: t.pos });
+            }
+        }
+    }
+
+    fn down(&mut self, id: i32, pos: [f32; 2], time: f64, out: &mut Vec<Gesture>) {
+        if self.tracks.iter().any(|t| t.id == id) {
+            return;
+        }
+
+        if self.tracks.is_empty() {
+            self.multi = false;
+            self.long_pressed = false;
+        }
+
+        self.tracks.push(Track {
+            id,
+            start: pos,
+            start_time: time,
+            pos,
+            moved: false,
+        });
+
+        if self.tracks.len() == 2 {
+            self.multi = true;
+            self.end_pan(out);
+            self.two = Some(self.measure_two(Two::default()));
+        } else if self.tracks.len() > 2 {
+            //Pinch and rotate only follow two fingers.
+            self.end_two(out);
+        }
+    }
+
+    fn moved(&mut self, id: i32, pos: [f32; 2], out: &mut Vec<Gesture>) {
+        let slop = self.config.tap_slop;
+        let Some(t) = self.tracks.iter_mut().find(|t| t.id == id) else {
+            return;
+        };
+        let last = t.pos;
+        t.pos = pos;
+        if len(sub(pos, t.start)) > slop {
+            t.moved = true;
+        }
+        let t = *t;
+
+        match self.tracks.len() {
+            1 if t.moved && !self.long_pressed => {
+                if self.panning {
+                    out.push(Gesture::Pan {
+                        phase: Phase::Move,
+                        pos,
+                        delta: sub(pos, last),
+                    });
+                } else {
+                    self.panning = true;
+                    out.push(Gesture::Pan {
+                        phase: Phase::Start,
+                        pos,
+                        delta: sub(pos, t.start),
+                    });
+                }
+            }
+            2 => {
+                let Some(mut two) = self.two else {
+                    return;
+                };
+                let now = self.measure_two(two);
+                let center = self.center();
+                let scale = if two.dist > 0.0 {
+                    now.dist / two.dist
+                } else {
+                    1.0
+                };
+                let angle = wrap_angle(now.angle - two.angle);
+
+                if two.pinching || (scale - 1.0).abs() > self.config.pinch_threshold {
+                    let phase = if two.pinching {
+                        Phase::Move
+                    } else {
+                        Phase::Start
+                    };
+                    two.pinching = true;
+                    out.push(Gesture::Pinch {
+                        phase,
+                        center,
+                        scale,
+                    });
+                }
+
+                if two.rotating || angle.abs() > self.config.rotate_threshold {
+                    let phase = if two.rotating {
+                        Phase::Move
+                    } else {
+                        Phase::Start
+                    };
+                    two.rotating = true;
+                    out.push(Gesture::Rotate {
+                        phase,
+                        center,
+                        angle,
+                    });
+                }
+
+                self.two = Some(two);
+            }
+            _ => {}
+        }
+    }
+
+    fn up(&mut self, id: i32, pos: [f32; 2], time: f64, cancel: bool, out: &mut Vec<Gesture>) {
+        let Some(index) = self.tracks.iter().position(|t| t.id == id) else {
+            return;
+        };
+
+        if self.tracks.len() >= 2 {
+            self.end_two(out);
+        }
+
+        let mut t = self.tracks.remove(index);
+        t.pos = pos;
+
+        if self.tracks.is_empty() {
+            if self.panning {
+                self.end_pan(out);
+            } else if !cancel
+                && !t.moved
+                && !self.multi
+                && !self.long_pressed
+                && time - t.start_time <= self.config.tap_max_duration
+            {
+                self.tap(pos, time, out);
+            }
+        } else if self.tracks.len() == 1 {
+            //The remaining finger can start a new pan from where it is now.
+            let r = &mut self.tracks[0];
+            r.start = r.pos;
+            r.moved = false;
+        } else if self.tracks.len() >= 2 {
+            self.two = Some(self.measure_two(Two::default()));
+        }
+    }
+
+    fn tap(&mut self, pos: [f32; 2], time: f64, out: &mut Vec<Gesture>) {
+        match self.last_tap.take() {
+            Some((last_time, last_pos))
+                if time - last_time <= self.config.double_tap_max_interval
+                    && len(sub(pos, last_pos)) <= self.config.double_tap_max_distance =>
+            {
+                out.push(Gesture::DoubleTap { pos });
+            }
+            _ => {
+                out.push(Gesture::Tap { pos });
+                self.last_tap = Some((time, pos));
+            }
+        }
+    }
+
+    fn end_pan(&mut self, out: &mut Vec<Gesture>) {
+        if self.panning {
+            self.panning = false;
+            let pos = self.tracks.first().map(|t| t.pos).unwrap_or([0.0; 2]);
+            out.push(Gesture::Pan {
+                phase: Phase::End,
+                pos,
+                delta: [0.0; 2],
+            });
+        }
+    }
+
+    fn end_two(&mut self, out: &mut Vec<Gesture>) {
+        let center = self.center();
+        if let Some(two) = self.two.take() {
+            let now = self.measure_two(two);
+            if two.pinching {
+                let scale = if two.dist > 0.0 {
+                    now.dist / two.dist
+                } else {
+                    1.0
+                };
+                out.push(Gesture::Pinch {
+                    phase: Phase::End,
+                    center,
+                    scale,
+                });
+            }
+            if two.rotating {
+                out.push(Gesture::Rotate {
+                    phase: Phase::End,
+                    center,
+                    angle: wrap_angle(now.angle - two.angle),
+                });
+            }
+        }
+    }
+
+    fn measure_two(&self, two: Two) -> Two {
+        let d = sub(self.tracks[1].pos, self.tracks[0].pos);
+        Two {
+            dist: len(d),
+            angle: d[1].atan2(d[0]),
+            ..two
+        }
+    }
+
+    fn center(&self) -> [f32; 2] {
+        let [a, b] = [self.tracks[0].pos, self.tracks[1].pos];
+        [(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Touch;
+
+    fn touches(t: &[(i32, [f32; 2])]) -> Vec<Touch> {
+        t.iter().map(|&(id, pos)| Touch { id, pos }).collect()
+    }
+
+    fn start(g: &mut GestureRecognizer, t: &[(i32, [f32; 2])], time: f64) -> Vec<Gesture> {
+        let mut out = vec![];
+        let touches = touches(t);
+        g.handle(&InputEvent::TouchStart { touches }, time, &mut out);
+        out
+    }
+
+    fn moved(g: &mut GestureRecognizer, t: &[(i32, [f32; 2])], time: f64) -> Vec<Gesture> {
+        let mut out = vec![];
+        let touches = touches(t);
+        g.handle(&InputEvent::TouchMove { touches }, time, &mut out);
+        out
+    }
+
+    fn end(g: &mut GestureRecognizer, t: &[(i32, [f32; 2])], time: f64) -> Vec<Gesture> {
+        let mut out = vec![];
+        let touches = touches(t);
+        g.handle(&InputEvent::TouchEnd { touches }, time, &mut out);
+        out
+    }
+
+    fn recognizer() -> GestureRecognizer {
+        GestureRecognizer::new(GestureConfig::default())
+    }
+
+    //Only the kind and phase of each gesture.
+    fn phases(gestures: &[Gesture]) -> Vec<(&'static str, Option<Phase>)> {
+        gestures
+            .iter()
+            .map(|g| match *g {
+                Gesture::Tap { .. } => ("tap", None),
+                Gesture::DoubleTap { .. } => ("double_tap", None),
+                Gesture::LongPress { .. } => ("long_press", None),
+                Gesture::Pan { phase, .. } => ("pan", Some(phase)),
+                Gesture::Pinch { phase, .. } => ("pinch", Some(phase)),
+                Gesture::Rotate { phase, .. } => ("rotate", Some(phase)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tap() {
+        let mut g = recognizer();
+        assert!(start(&mut g, &[(0, [5.0, 5.0])], 0.0).is_empty());
+        assert_eq!(
+            end(&mut g, &[(0, [6.0, 5.0])], 100.0),
+            vec![Gesture::Tap { pos: [6.0, 5.0] }]
+        );
+    }
+
+    #[test]
+    fn slow_release_is_not_a_tap() {
+        let mut g = recognizer();
+        start(&mut g, &[(0, [5.0, 5.0])], 0.0);
+        assert!(end(&mut g, &[(0, [5.0, 5.0])], 400.0).is_empty());
+    }
+
+    #[test]
+    fn double_tap() {
+        let mut g = recognizer();
+        start(&mut g, &[(0, [5.0, 5.0])], 0.0);
+        end(&mut g, &[(0, [5.0, 5.0])], 50.0);
+        start(&mut g, &[(1, [8.0, 5.0])], 200.0);
+        assert_eq!(
+            end(&mut g, &[(1, [8.0, 5.0])], 250.0),
+            vec![Gesture::DoubleTap { pos: [8.0, 5.0] }]
+        );
+
+        //A third tap starts over.
+        start(&mut g, &[(2, [8.0, 5.0])], 300.0);
+        assert_eq!(
+            end(&mut g, &[(2, [8.0, 5.0])], 350.0),
+            vec![Gesture::Tap { pos: [8.0, 5.0] }]
+        );
+    }
+
+    #[test]
+    fn long_press() {
+        let mut g = recognizer();
+        let mut out = vec![];
+        start(&mut g, &[(0, [5.0, 5.0])], 0.0);
+        g.update(499.0, &mut out);
+        assert!(out.is_empty());
+        g.update(500.0, &mut out);
+        assert_eq!(out, vec![Gesture::LongPress { pos: [5.0, 5.0] }]);
+
+        //Only once, and no tap on release.
+        out.clear();
+        g.update(1000.0, &mut out);
+        assert!(out.is_empty());
+        assert!(end(&mut g, &[(0, [5.0, 5.0])], 1100.0).is_empty());
+    }
+
+    #[test]
+    fn pan() {
+        let mut g = recognizer();
+        start(&mut g, &[(0, [0.0, 0.0])], 0.0);
+        assert!(moved(&mut g, &[(0, [5.0, 0.0])], 10.0).is_empty());
+        assert_eq!(
+            moved(&mut g, &[(0, [20.0, 0.0])], 20.0),
+            vec![Gesture::Pan {
+                phase: Phase::Start,
+                pos: [20.0, 0.0],
+                delta: [20.0, 0.0],
+            }]
+        );
+        assert_eq!(
+            moved(&mut g, &[(0, [25.0, 2.0])], 30.0),
+            vec![Gesture::Pan {
+                phase: Phase::Move,
+                pos: [25.0, 2.0],
+                delta: [5.0, 2.0],
+            }]
+        );
+        assert_eq!(
+            phases(&end(&mut g, &[(0, [25.0, 2.0])], 40.0)),
+            vec![("pan", Some(Phase::End))]
+        );
+    }
+
+    #[test]
+    fn pinch_and_rotate_phases() {
+        let mut g = recognizer();
+        start(&mut g, &[(0, [0.0, 0.0]), (1, [100.0, 0.0])], 0.0);
+
+        let out = moved(&mut g, &[(1, [200.0, 0.0])], 10.0);
+        assert_eq!(phases(&out), vec![("pinch", Some(Phase::Start))]);
+        let Gesture::Pinch { scale, center, .. } = out[0] else {
+            unreachable!()
+        };
+        assert_eq!(scale, 2.0);
+        assert_eq!(center, [100.0, 0.0]);
+
+        let out = moved(&mut g, &[(1, [0.0, 200.0])], 20.0);
+        assert_eq!(
+            phases(&out),
+            vec![("pinch", Some(Phase::Move)), ("rotate", Some(Phase::Start))]
+        );
+
+        assert_eq!(
+            phases(&end(&mut g, &[(1, [0.0, 200.0])], 30.0)),
+            vec![("pinch", Some(Phase::End)), ("rotate", Some(Phase::End))]
+        );
+        //Lifting the last finger doesn't tap.
+        assert!(end(&mut g, &[(0, [0.0, 0.0])], 40.0).is_empty());
+    }
+
+    #[test]
+    fn third_finger_ends_pinch() {
+        let mut g = recognizer();
+        start(&mut g, &[(0, [0.0, 0.0]), (1, [100.0, 0.0])], 0.0);
+        moved(&mut g, &[(1, [200.0, 0.0])], 10.0);
+
+        assert_eq!(
+            phases(&start(&mut g, &[(2, [50.0, 50.0])], 20.0)),
+            vec![("pinch", Some(Phase::End))]
+        );
+        assert!(moved(&mut g, &[(1, [300.0, 0.0])], 30.0).is_empty());
+
+        //Back to two fingers, a new pinch starts.
+        assert!(end(&mut g, &[(2, [50.0, 50.0])], 40.0).is_empty());
+        assert_eq!(
+            phases(&moved(&mut g, &[(1, [600.0, 0.0])], 50.0)),
+            vec![("pinch", Some(Phase::Start))]
+        );
+        assert_eq!(
+            phases(&end(&mut g, &[(0, [0.0, 0.0])], 60.0)),
+            vec![("pinch", Some(Phase::End))]
+        );
+    }
+
+    #[test]
+    fn lifting_one_of_three_fingers_ends_pinch_once() {
+        let mut g = recognizer();
+        start(&mut g, &[(0, [0.0, 0.0]), (1, [100.0, 0.0])], 0.0);
+        moved(&mut g, &[(1, [200.0, 0.0])], 10.0);
+        start(&mut g, &[(2, [50.0, 50.0])], 20.0);
+        //Already ended when the third finger landed.
+        assert!(end(&mut g, &[(0, [0.0, 0.0])], 30.0).is_empty());
+        assert_eq!(
+            phases(&moved(&mut g, &[(1, [400.0, 0.0])], 40.0)),
+            vec![
+                ("pinch", Some(Phase::Start)),
+                ("rotate", Some(Phase::Start))
+            ]
+        );
+    }
+}
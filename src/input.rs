@@ -0,0 +1,504 @@
+//!
+//! Serializable input events that can be forwarded from the main thread to the worker,
+//! either directly as the message type or as part of it.
+//!
+//! ```ignore
+//! let _handle = sender.register_event(&canvas, "mousemove", |e| {
+//!     InputEvent::from_event(e.event).map(MEvent::Input)
+//! });
+//! ```
+//!
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsCast;
+
+///
+/// Keyboard modifiers held when the event fired.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub meta: bool,
+}
+
+///
+/// A mouse button as reported by `MouseEvent.button`.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    Back,
+    Forward,
+    Other(i16),
+}
+impl MouseButton {
+    pub fn from_button(button: i16) -> MouseButton {
+        match button {
+            0 => MouseButton::Left,
+            1 => MouseButton::Middle,
+            2 => MouseButton::Right,
+            3 => MouseButton::Back,
+            4 => MouseButton::Forward,
+            a => MouseButton::Other(a),
+        }
+    }
+
+    ///
+    /// The bit of the button in `MouseEvent.buttons`, or 0 for [`MouseButton::Other`].
+    ///
+    pub fn bit(self) -> u16 {
+        match self {
+            MouseButton::Left => 1,
+            MouseButton::Right => 2,
+            MouseButton::Middle => 4,
+            MouseButton::Back => 8,
+            MouseButton::Forward => 16,
+            MouseButton::Other(_) => 0,
+        }
+    }
+}
+
+///
+/// The unit of a wheel delta.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WheelMode {
+    Pixel,
+    Line,
+    Page,
+}
+
+///
+/// The kind of device that produced a pointer event, as in `PointerEvent.pointerType`.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PointerKind {
+    Mouse,
+    Pen,
+    Touch,
+    Other,
+}
+
+///
+/// Information about the pointer that produced an event.
+/// Mouse events are reported as the primary mouse pointer.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Pointer {
+    pub id: i32,
+    pub kind: PointerKind,
+    pub primary: bool,
+    ///
+    /// Normalized pressure in the range `[0,1]`.
+    ///
+    pub pressure: f32,
+    ///
+    /// Tilt in degrees along the x and y axis.
+    ///
+    pub tilt: [i32; 2],
+}
+impl Pointer {
+    fn from_mouse_event(event: &web_sys::MouseEvent) -> Pointer {
+        Pointer {
+            id: 1,
+            kind: PointerKind::Mouse,
+            primary: true,
+            pressure: if event.buttons() != 0 { 0.5 } else { 0.0 },
+            tilt: [0, 0],
+        }
+    }
+
+    fn from_pointer_event(event: &web_sys::PointerEvent) -> Pointer {
+        let kind = match event.pointer_type().as_str() {
+            "mouse" => PointerKind::Mouse,
+            "pen" => PointerKind::Pen,
+            "touch" => PointerKind::Touch,
+            _ => PointerKind::Other,
+        };
+        Pointer {
+            id: event.pointer_id(),
+            kind,
+            primary: event.is_primary(),
+            pressure: event.pressure(),
+            tilt: [event.tilt_x(), event.tilt_y()],
+        }
+    }
+}
+
+///
+/// A single touch point.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Touch {
+    pub id: i32,
+    pub pos: [f32; 2],
+}
+
+///
+/// A serializable input event. Positions are in client coordinates unless converted
+/// with [`InputEvent::map_pos`] or produced by [`EventData::input`](crate::EventData::input).
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum InputEvent {
+    PointerDown {
+        pos: [f32; 2],
+        button: MouseButton,
+        pointer: Pointer,
+        modifiers: Modifiers,
+    },
+    PointerMove {
+        pos: [f32; 2],
+        ///
+        /// `movementX/Y`, the distance moved since the last move event.
+        /// Unlike `pos`, this keeps changing while the pointer is locked.
+        ///
+        movement: [f32; 2],
+        ///
+        /// `MouseEvent.buttons`, the buttons held as a bitmask. Pointer events only
+        /// fire `pointerdown` for the first button pressed and `pointerup` for the
+        /// last one released, so the others only show up here.
+        ///
+        buttons: u16,
+        pointer: Pointer,
+        modifiers: Modifiers,
+    },
+    PointerUp {
+        pos: [f32; 2],
+        button: MouseButton,
+        pointer: Pointer,
+        modifiers: Modifiers,
+    },
+    ///
+    /// The browser stopped sending events for this pointer, e.g. because
+    /// a touch turned into a scroll.
+    ///
+    PointerCancel {
+        pos: [f32; 2],
+        pointer: Pointer,
+    },
+    ///
+    /// The touches that changed, as in `TouchEvent.changedTouches`.
+    ///
+    TouchStart {
+        touches: Vec<Touch>,
+    },
+    TouchMove {
+        touches: Vec<Touch>,
+    },
+    TouchEnd {
+        touches: Vec<Touch>,
+    },
+    TouchCancel {
+        touches: Vec<Touch>,
+    },
+    KeyDown {
+        code: String,
+        key: String,
+        repeat: bool,
+        modifiers: Modifiers,
+    },
+    KeyUp {
+        code: String,
+        key: String,
+        modifiers: Modifiers,
+    },
+    Wheel {
+        pos: [f32; 2],
+        delta: [f32; 2],
+        mode: WheelMode,
+        modifiers: Modifiers,
+    },
+}
+
+impl InputEvent {
+    ///
+    /// Apply a function to every position in the event.
+    ///
+    pub fn map_pos(&mut self, mut func: impl FnMut([f32; 2]) -> [f32; 2]) {
+        match self {
+            InputEvent::PointerMove { pos, movement, .. } => {
+                //The conversion is linear, so map both ends of the movement.
+                let start = func(*pos);
+                let end = func([pos[0] + movement[0], pos[1] + movement[1]]);
+                *movement = [end[0] - start[0], end[1] - start[1]];
+                *pos = start;
+            }
+            InputEvent::PointerDown { pos, .. }
+            | InputEvent::PointerUp { pos, .. }
+            | InputEvent::PointerCancel { pos, .. }
+            | InputEvent::Wheel { pos, .. } => *pos = func(*pos),
+            InputEvent::TouchStart { touches }
+            | InputEvent::TouchMove { touches }
+            | InputEvent::TouchEnd { touches }
+            | InputEvent::TouchCancel { touches } => {
+                for t in touches.iter_mut() {
+                    t.pos = func(t.pos);
+                }
+            }
+            InputEvent::KeyDown { .. } | InputEvent::KeyUp { .. } => {}
+        }
+    }
+
+    ///
+    /// Convert any supported event based on its type.
+    /// Returns `None` for event types that have no equivalent.
+    ///
+    pub fn from_event(event: &web_sys::Event) -> Option<InputEvent> {
+        match event.type_().as_str() {
+            "wheel" => Some(Self::from_wheel_event(event.dyn_ref()?)),
+            "mousedown" | "mousemove" | "mouseup" => Self::from_mouse_event(event.dyn_ref()?),
+            "pointerdown" | "pointermove" | "pointerup" | "pointercancel" => {
+                Self::from_pointer_event(event.dyn_ref()?)
+            }
+            "touchstart" | "touchmove" | "touchend" | "touchcancel" => {
+                Self::from_touch_event(event.dyn_ref()?)
+            }
+            "keydown" | "keyup" => Self::from_keyboard_event(event.dyn_ref()?),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Convert a `mousedown`, `mousemove` or `mouseup` event.
+    ///
+    pub fn from_mouse_event(event: &web_sys::MouseEvent) -> Option<InputEvent> {
+        let pos = [event.client_x() as f32, event.client_y() as f32];
+        let button = MouseButton::from_button(event.button());
+        let pointer = Pointer::from_mouse_event(event);
+        let modifiers = Modifiers {
+            shift: event.shift_key(),
+            ctrl: event.ctrl_key(),
+            alt: event.alt_key(),
+            meta: event.meta_key(),
+        };
+
+        Some(match event.type_().as_str() {
+            "mousedown" => InputEvent::PointerDown {
+                pos,
+                button,
+                pointer,
+                modifiers,
+            },
+            "mousemove" => InputEvent::PointerMove {
+                pos,
+                movement: [event.movement_x() as f32, event.movement_y() as f32],
+                buttons: event.buttons(),
+                pointer,
+                modifiers,
+            },
+            "mouseup" => InputEvent::PointerUp {
+                pos,
+                button,
+                pointer,
+                modifiers,
+            },
+            _ => return None,
+        })
+    }
+
+    ///
+    /// Convert a `pointerdown`, `pointermove`, `pointerup` or `pointercancel` event.
+    ///
+    pub fn from_pointer_event(event: &web_sys::PointerEvent) -> Option<InputEvent> {
+        let pos = [event.client_x() as f32, event.client_y() as f32];
+        let button = MouseButton::from_button(event.button());
+        let pointer = Pointer::from_pointer_event(event);
+        let modifiers = Modifiers {
+            shift: event.shift_key(),
+            ctrl: event.ctrl_key(),
+            alt: event.alt_key(),
+            meta: event.meta_key(),
+        };
+
+        Some(match event.type_().as_str() {
+            "pointerdown" => InputEvent::PointerDown {
+                pos,
+                button,
+                pointer,
+                modifiers,
+            },
+            "pointermove" => InputEvent::PointerMove {
+                pos,
+                movement: [event.movement_x() as f32, event.movement_y() as f32],
+                buttons: event.buttons(),
+                pointer,
+                modifiers,
+            },
+            "pointerup" => InputEvent::PointerUp {
+                pos,
+                button,
+                pointer,
+                modifiers,
+            },
+            "pointercancel" => InputEvent::PointerCancel { pos, pointer },
+            _ => return None,
+        })
+    }
+
+    ///
+    /// Same as [`InputEvent::from_pointer_event`], except a `pointermove` is expanded into all
+    /// of the coalesced events that the browser merged into it, oldest first.
+    ///
+    pub fn from_pointer_event_coalesced(event: &web_sys::PointerEvent) -> Vec<InputEvent> {
+        let has_coalesced =
+            js_sys::Reflect::has(event, &wasm_bindgen::JsValue::from_str("getCoalescedEvents"))
+                .unwrap_or(false);
+
+        if event.type_() == "pointermove" && has_coalesced {
+            let events: Vec<_> = event
+                .get_coalesced_events()
+                .iter()
+                .filter_map(|e| e.dyn_into::<web_sys::PointerEvent>().ok())
+                .filter_map(|e| Self::from_pointer_event(&e))
+                .collect();
+
+            if !events.is_empty() {
+                return events;
+            }
+        }
+
+        Self::from_pointer_event(event).into_iter().collect()
+    }
+
+    ///
+    /// Convert a `touchstart`, `touchmove`, `touchend` or `touchcancel` event.
+    ///
+    pub fn from_touch_event(event: &web_sys::TouchEvent) -> Option<InputEvent> {
+        let list = event.changed_touches();
+        let touches = (0..list.length())
+            .filter_map(|i| list.get(i))
+            .map(|t| Touch {
+                id: t.identifier(),
+                pos: [t.client_x() as f32, t.client_y() as f32],
+            })
+            .collect();
+
+        Some(match event.type_().as_str() {
+            "touchstart" => InputEvent::TouchStart { touches },
+            "touchmove" => InputEvent::TouchMove { touches },
+            "touchend" => InputEvent::TouchEnd { touches },
+            "touchcancel" => InputEvent::TouchCancel { touches },
+            _ => return None,
+        })
+    }
+
+    ///
+    /// Convert a `keydown` or `keyup` event.
+    ///
+    pub fn from_keyboard_event(event: &web_sys::KeyboardEvent) -> Option<InputEvent> {
+        let modifiers = Modifiers {
+            shift: event.shift_key(),
+            ctrl: event.ctrl_key(),
+            alt: event.alt_key(),
+            meta: event.meta_key(),
+        };
+
+        Some(match event.type_().as_str() {
+            "keydown" => InputEvent::KeyDown {
+                code: event.code(),
+                key: event.key(),
+                repeat: event.repeat(),
+                modifiers,
+            },
+            "keyup" => InputEvent::KeyUp {
+                code: event.code(),
+                key: event.key(),
+                modifiers,
+            },
+            _ => return None,
+        })
+    }
+
+    ///
+    /// Convert a `wheel` event.
+    ///
+    pub fn from_wheel_event(event: &web_sys::WheelEvent) -> InputEvent {
+        let mode = match event.delta_mode() {
+            web_sys::WheelEvent::DOM_DELTA_LINE => WheelMode::Line,
+            web_sys::WheelEvent::DOM_DELTA_PAGE => WheelMode::Page,
+            _ => WheelMode::Pixel,
+        };
+
+        InputEvent::Wheel {
+            pos: [event.client_x() as f32, event.client_y() as f32],
+            delta: [event.delta_x() as f32, event.delta_y() as f32],
+            mode,
+            modifiers: Modifiers {
+                shift: event.shift_key(),
+                ctrl: event.ctrl_key(),
+                alt: event.alt_key(),
+                meta: event.meta_key(),
+            },
+        }
+    }
+}
+
+///
+/// What size the canvas backing store is assumed to be when converting coordinates.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backing {
+    ///
+    /// The `width` and `height` attributes of the canvas element.
+    ///
+    Attributes,
+    ///
+    /// The css content size of the element multiplied by `devicePixelRatio`.
+    ///
+    DevicePixelRatio,
+    ///
+    /// An explicit backing store size.
+    ///
+    Size([f32; 2]),
+}
+
+///
+/// Convert a position in client coordinates (e.g. `clientX`/`clientY`) into the backing store
+/// coordinates of the element. Takes into account where the element is on the page,
+/// its border and padding, and how much css has stretched it.
+///
+pub fn convert_coord(elem: &web_sys::Element, client: [f32; 2], backing: Backing) -> [f32; 2] {
+    let rect = elem.get_bounding_client_rect();
+
+    let style = gloo::utils::window().get_computed_style(elem).ok().flatten();
+    let padding = |name: &str| {
+        style
+            .as_ref()
+            .and_then(|s| s.get_property_value(name).ok())
+            .and_then(|v| v.trim_end_matches("px").parse::<f32>().ok())
+            .unwrap_or(0.0)
+    };
+    let [pad_left, pad_right, pad_top, pad_bottom] = [
+        padding("padding-left"),
+        padding("padding-right"),
+        padding("padding-top"),
+        padding("padding-bottom"),
+    ];
+
+    let content = [
+        elem.client_width() as f32 - pad_left - pad_right,
+        elem.client_height() as f32 - pad_top - pad_bottom,
+    ];
+
+    let x = client[0] - rect.left() as f32 - elem.client_left() as f32 - pad_left;
+    let y = client[1] - rect.top() as f32 - elem.client_top() as f32 - pad_top;
+
+    let dim = match backing {
+        Backing::Attributes => match elem.dyn_ref::<web_sys::HtmlCanvasElement>() {
+            Some(c) => [c.width() as f32, c.height() as f32],
+            None => content,
+        },
+        Backing::DevicePixelRatio => {
+            let dpr = gloo::utils::window().device_pixel_ratio() as f32;
+            [content[0] * dpr, content[1] * dpr]
+        }
+        Backing::Size(dim) => dim,
+    };
+
+    let scale = |d: f32, c: f32| if c > 0.0 { d / c } else { 1.0 };
+
+    [x * scale(dim[0], content[0]), y * scale(dim[1], content[1])]
+}
@@ -0,0 +1,551 @@
+//!
+//! Keyboard, mouse, touch and gamepad state on the worker, built up from forwarded
+//! [`InputEvent`]s and [`GamepadEvent`]s.
+//!
+//! ```ignore
+//! let mut state = InputState::new();
+//! loop {
+//!     for e in events.drain(..) {
+//!         if let MEvent::Input(e) = e {
+//!             state.handle(&e);
+//!         }
+//!     }
+//!     if state.just_pressed("Space") {
+//!         jump();
+//!     }
+//!     state.end_frame();
+//! }
+//! ```
+//!
+use crate::gamepad::GamepadEvent;
+use crate::input::{InputEvent, MouseButton, Touch};
+use crate::lifecycle::Lifecycle;
+use std::collections::{HashMap, HashSet};
+
+///
+/// Tracks which keys and buttons are held, which changed since the last
+/// call to [`InputState::end_frame`], where the mouse is, and which touches are active.
+///
+/// Keys are identified by `KeyboardEvent.code` (e.g. `"KeyW"`, `"Space"`)
+/// so they don't depend on the keyboard layout.
+///
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    keys: HashSet<String>,
+    keys_pressed: HashSet<String>,
+    keys_released: HashSet<String>,
+    buttons: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    mouse_pos: Option<[f32; 2]>,
+    mouse_delta: [f32; 2],
+    wheel: [f32; 2],
+    touches: Vec<Touch>,
+    touches_started: Vec<Touch>,
+    touches_ended: Vec<Touch>,
+    pad_buttons: HashSet<(u32, u32)>,
+    pad_buttons_pressed: HashSet<(u32, u32)>,
+    pad_buttons_released: HashSet<(u32, u32)>,
+    pad_axes: HashMap<(u32, u32), f32>,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Update the state with an event.
+    ///
+    pub fn handle(&mut self, event: &InputEvent) {
+        match event {
+            InputEvent::KeyDown { code, .. } => self.press_key(code),
+            InputEvent::KeyUp { code, .. } => self.release_key(code),
+            InputEvent::PointerDown {
+                pos,
+                button,
+                pointer,
+                ..
+            } if pointer.primary => {
+                self.mouse_pos = Some(*pos);
+                self.press_button(*button);
+            }
+            InputEvent::PointerUp {
+                pos,
+                button,
+                pointer,
+                ..
+            } if pointer.primary => {
+                self.mouse_pos = Some(*pos);
+                self.release_button(*button);
+            }
+            InputEvent::PointerMove {
+                pos,
+                movement,
+                buttons,
+                pointer,
+                ..
+            } if pointer.primary => {
+                self.mouse_pos = Some(*pos);
+                self.mouse_delta[0] += movement[0];
+                self.mouse_delta[1] += movement[1];
+                //Buttons pressed or released while another is held only show up here.
+                for b in [
+                    MouseButton::Left,
+                    MouseButton::Middle,
+                    MouseButton::Right,
+                    MouseButton::Back,
+                    MouseButton::Forward,
+                ] {
+                    if buttons & b.bit() != 0 {
+                        self.press_button(b);
+                    } else {
+                        self.release_button(b);
+                    }
+                }
+            }
+            InputEvent::PointerCancel { pointer, .. } if pointer.primary => {
+                self.release_buttons();
+            }
+            InputEvent::Wheel { delta, .. } => {
+                self.wheel[0] += delta[0];
+                self.wheel[1] += delta[1];
+            }
+            InputEvent::TouchStart { touches } => {
+                self.touches_started.extend_from_slice(touches);
+                for t in touches {
+                    self.touches.retain(|a| a.id != t.id);
+                    self.touches.push(*t);
+                }
+            }
+            InputEvent::TouchMove { touches } => {
+                for t in touches {
+                    if let Some(a) = self.touches.iter_mut().find(|a| a.id == t.id) {
+                        a.pos = t.pos;
+                    }
+                }
+            }
+            InputEvent::TouchEnd { touches } | InputEvent::TouchCancel { touches } => {
+                self.touches_ended.extend_from_slice(touches);
+                self.touches.retain(|a| touches.iter().all(|t| t.id != a.id));
+            }
+            _ => {}
+        }
+    }
+
+    ///
+    /// Update the state with a gamepad event.
+    ///
+    pub fn handle_gamepad(&mut self, event: &GamepadEvent) {
+        match *event {
+            GamepadEvent::Connected { .. } => {}
+            GamepadEvent::Disconnected { index } => self.release_gamepad(index),
+            GamepadEvent::Button {
+                index,
+                button,
+                pressed,
+                ..
+            } => {
+                let key = (index, button);
+                if pressed {
+                    if self.pad_buttons.insert(key) {
+                        self.pad_buttons_pressed.insert(key);
+                    }
+                } else if self.pad_buttons.remove(&key) {
+                    self.pad_buttons_released.insert(key);
+                }
+            }
+            GamepadEvent::Axis { index, axis, value } => {
+                self.pad_axes.insert((index, axis), value);
+            }
+        }
+    }
+
+    fn release_gamepad(&mut self, index: u32) {
+        let released: Vec<_> = self
+            .pad_buttons
+            .iter()
+            .copied()
+            .filter(|&(i, _)| i == index)
+            .collect();
+        for key in released {
+            self.pad_buttons.remove(&key);
+            self.pad_buttons_released.insert(key);
+        }
+        self.pad_axes.retain(|&(i, _), _| i != index);
+    }
+
+    fn press_key(&mut self, code: &str) {
+        if self.keys.insert(code.to_owned()) {
+            self.keys_pressed.insert(code.to_owned());
+        }
+    }
+
+    fn release_key(&mut self, code: &str) {
+        if self.keys.remove(code) {
+            self.keys_released.insert(code.to_owned());
+        }
+    }
+
+    fn press_button(&mut self, button: MouseButton) {
+        if self.buttons.insert(button) {
+            self.buttons_pressed.insert(button);
+        }
+    }
+
+    fn release_button(&mut self, button: MouseButton) {
+        if self.buttons.remove(&button) {
+            self.buttons_released.insert(button);
+        }
+    }
+
+    fn release_buttons(&mut self) {
+        self.buttons_released.extend(self.buttons.drain());
+    }
+
+    ///
+    /// Release every held key, button and touch, as if they were all let go.
+    /// Useful when the page loses focus and the key up events will never arrive.
+    ///
+    pub fn release_all(&mut self) {
+        self.keys_released.extend(self.keys.drain());
+        self.release_buttons();
+        self.touches_ended.append(&mut self.touches);
+        self.pad_buttons_released.extend(self.pad_buttons.drain());
+        self.pad_axes.clear();
+    }
+
+    ///
+    /// Release everything that is held when the page loses focus or is hidden,
+    /// since the matching key up events will never arrive.
+    ///
+    pub fn handle_lifecycle(&mut self, event: &Lifecycle) {
+        match event {
+            Lifecycle::Hidden | Lifecycle::Blur | Lifecycle::PageHide { .. } => self.release_all(),
+            _ => {}
+        }
+    }
+
+    ///
+    /// Clear the per frame state. Call once at the end of every frame.
+    ///
+    pub fn end_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.mouse_delta = [0.0; 2];
+        self.wheel = [0.0; 2];
+        self.touches_started.clear();
+        self.touches_ended.clear();
+        self.pad_buttons_pressed.clear();
+        self.pad_buttons_released.clear();
+    }
+
+    ///
+    /// True if the key is held down.
+    ///
+    pub fn pressed(&self, code: &str) -> bool {
+        self.keys.contains(code)
+    }
+
+    ///
+    /// True if the key went down this frame. Key repeats are not counted.
+    ///
+    pub fn just_pressed(&self, code: &str) -> bool {
+        self.keys_pressed.contains(code)
+    }
+
+    ///
+    /// True if the key went up this frame.
+    ///
+    pub fn just_released(&self, code: &str) -> bool {
+        self.keys_released.contains(code)
+    }
+
+    ///
+    /// True if the mouse button is held down.
+    ///
+    pub fn mouse_pressed(&self, button: MouseButton) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    ///
+    /// True if the mouse button went down this frame.
+    ///
+    pub fn mouse_just_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    ///
+    /// True if the mouse button went up this frame.
+    ///
+    pub fn mouse_just_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    ///
+    /// The last known position of the primary pointer.
+    /// `None` until the first pointer event arrives.
+    ///
+    pub fn mouse_pos(&self) -> Option<[f32; 2]> {
+        self.mouse_pos
+    }
+
+    ///
+    /// How far the primary pointer moved this frame. Summed from the movement of each
+    /// move event, so it still works while the pointer is locked.
+    ///
+    pub fn mouse_delta(&self) -> [f32; 2] {
+        self.mouse_delta
+    }
+
+    ///
+    /// The wheel deltas accumulated this frame, in whatever unit the browser reported.
+    ///
+    pub fn wheel(&self) -> [f32; 2] {
+        self.wheel
+    }
+
+    ///
+    /// The touches currently on the screen, in the order they started.
+    ///
+    pub fn touches(&self) -> &[Touch] {
+        &self.touches
+    }
+
+    ///
+    /// The touches that started this frame.
+    ///
+    pub fn touches_started(&self) -> &[Touch] {
+        &self.touches_started
+    }
+
+    ///
+    /// The touches that ended or were cancelled this frame, with their last position.
+    ///
+    pub fn touches_ended(&self) -> &[Touch] {
+        &self.touches_ended
+    }
+
+    ///
+    /// True if the button is held on the gamepad at `pad`, or on any gamepad if `None`.
+    ///
+    pub fn gamepad_pressed(&self, pad: Option<u32>, button: u32) -> bool {
+        Self::any_pad(&self.pad_buttons, pad, button)
+    }
+
+    ///
+    /// True if the gamepad button went down this frame.
+    ///
+    pub fn gamepad_just_pressed(&self, pad: Option<u32>, button: u32) -> bool {
+        Self::any_pad(&self.pad_buttons_pressed, pad, button)
+    }
+
+    ///
+    /// True if the gamepad button went up this frame.
+    ///
+    pub fn gamepad_just_released(&self, pad: Option<u32>, button: u32) -> bool {
+        Self::any_pad(&self.pad_buttons_released, pad, button)
+    }
+
+    fn any_pad(set: &HashSet<(u32, u32)>, pad: Option<u32>, button: u32) -> bool {
+        match pad {
+            Some(pad) => set.contains(&(pad, button)),
+            None => set.iter().any(|&(_, b)| b == button),
+        }
+    }
+
+    ///
+    /// The value of the axis on the gamepad at `pad`. If `None`, the value with the
+    /// largest magnitude across all gamepads.
+    ///
+    pub fn gamepad_axis(&self, pad: Option<u32>, axis: u32) -> f32 {
+        match pad {
+            Some(pad) => self.pad_axes.get(&(pad, axis)).copied().unwrap_or(0.0),
+            None => self
+                .pad_axes
+                .iter()
+                .filter(|(&(_, a), _)| a == axis)
+                .map(|(_, &v)| v)
+                .fold(0.0, |acc: f32, v| if v.abs() > acc.abs() { v } else { acc }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Modifiers, Pointer, PointerKind};
+
+    fn key_down(code: &str, repeat: bool) -> InputEvent {
+        InputEvent::KeyDown {
+            code: code.to_owned(),
+            key: String::new(),
+            repeat,
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    fn key_up(code: &str) -> InputEvent {
+        InputEvent::KeyUp {
+            code: code.to_owned(),
+            key: String::new(),
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    fn mouse() -> Pointer {
+        Pointer {
+            id: 1,
+            kind: PointerKind::Mouse,
+            primary: true,
+            pressure: 0.0,
+            tilt: [0, 0],
+        }
+    }
+
+    fn touch(id: i32) -> Touch {
+        Touch {
+            id,
+            pos: [id as f32, 0.0],
+        }
+    }
+
+    #[test]
+    fn just_pressed_lasts_one_frame() {
+        let mut s = InputState::new();
+        s.handle(&key_down("KeyW", false));
+        assert!(s.pressed("KeyW"));
+        assert!(s.just_pressed("KeyW"));
+
+        s.end_frame();
+        assert!(s.pressed("KeyW"));
+        assert!(!s.just_pressed("KeyW"));
+
+        s.handle(&key_up("KeyW"));
+        assert!(!s.pressed("KeyW"));
+        assert!(s.just_released("KeyW"));
+
+        s.end_frame();
+        assert!(!s.just_released("KeyW"));
+    }
+
+    #[test]
+    fn key_repeat_is_not_a_press() {
+        let mut s = InputState::new();
+        s.handle(&key_down("Space", false));
+        s.end_frame();
+        s.handle(&key_down("Space", true));
+        assert!(s.pressed("Space"));
+        assert!(!s.just_pressed("Space"));
+    }
+
+    #[test]
+    fn release_all() {
+        let mut s = InputState::new();
+        s.handle(&key_down("KeyA", false));
+        s.handle(&InputEvent::PointerDown {
+            pos: [0.0, 0.0],
+            button: MouseButton::Left,
+            pointer: mouse(),
+            modifiers: Modifiers::default(),
+        });
+        s.handle(&InputEvent::TouchStart {
+            touches: vec![touch(1)],
+        });
+        s.end_frame();
+
+        s.release_all();
+        assert!(!s.pressed("KeyA"));
+        assert!(s.just_released("KeyA"));
+        assert!(!s.mouse_pressed(MouseButton::Left));
+        assert!(s.mouse_just_released(MouseButton::Left));
+        assert!(s.touches().is_empty());
+        assert_eq!(s.touches_ended(), &[touch(1)]);
+    }
+
+    #[test]
+    fn touch_start_and_end() {
+        let mut s = InputState::new();
+        s.handle(&InputEvent::TouchStart {
+            touches: vec![touch(1), touch(2)],
+        });
+        assert_eq!(s.touches(), &[touch(1), touch(2)]);
+        assert_eq!(s.touches_started(), &[touch(1), touch(2)]);
+
+        s.end_frame();
+        assert!(s.touches_started().is_empty());
+
+        s.handle(&InputEvent::TouchEnd {
+            touches: vec![touch(1)],
+        });
+        assert_eq!(s.touches(), &[touch(2)]);
+        assert_eq!(s.touches_ended(), &[touch(1)]);
+
+        s.end_frame();
+        assert!(s.touches_ended().is_empty());
+    }
+
+    #[test]
+    fn mouse_delta_uses_movement() {
+        let mut s = InputState::new();
+        //While locked the position stays put, but the movement doesn't.
+        for _ in 0..2 {
+            s.handle(&InputEvent::PointerMove {
+                pos: [10.0, 10.0],
+                movement: [3.0, -1.0],
+                buttons: 0,
+                pointer: mouse(),
+                modifiers: Modifiers::default(),
+            });
+        }
+        assert_eq!(s.mouse_pos(), Some([10.0, 10.0]));
+        assert_eq!(s.mouse_delta(), [6.0, -2.0]);
+
+        s.end_frame();
+        assert_eq!(s.mouse_delta(), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn chorded_buttons_from_move() {
+        let moved = |buttons| InputEvent::PointerMove {
+            pos: [0.0, 0.0],
+            movement: [0.0, 0.0],
+            buttons,
+            pointer: mouse(),
+            modifiers: Modifiers::default(),
+        };
+        let mut s = InputState::new();
+        s.handle(&InputEvent::PointerDown {
+            pos: [0.0, 0.0],
+            button: MouseButton::Left,
+            pointer: mouse(),
+            modifiers: Modifiers::default(),
+        });
+        s.end_frame();
+
+        //Right pressed while left is held.
+        s.handle(&moved(1 | 2));
+        assert!(s.mouse_pressed(MouseButton::Left));
+        assert!(s.mouse_just_pressed(MouseButton::Right));
+        assert!(!s.mouse_just_pressed(MouseButton::Left));
+        s.end_frame();
+
+        //Left released while right is held.
+        s.handle(&moved(2));
+        assert!(s.mouse_just_released(MouseButton::Left));
+        assert!(s.mouse_pressed(MouseButton::Right));
+        s.end_frame();
+
+        s.handle(&InputEvent::PointerUp {
+            pos: [0.0, 0.0],
+            button: MouseButton::Right,
+            pointer: mouse(),
+            modifiers: Modifiers::default(),
+        });
+        assert!(s.mouse_just_released(MouseButton::Right));
+        s.handle(&moved(0));
+        assert!(!s.mouse_just_released(MouseButton::Left));
+    }
+}
@@ -11,6 +11,23 @@ use wasm_bindgen::JsCast;
 use gloo::utils::format::JsValueSerdeExt;
 
 //pub mod simple2d;
+pub mod input;
+pub mod input_state;
+pub mod gamepad;
+pub mod action;
+pub mod gesture;
+pub mod resize;
+pub mod lifecycle;
+pub mod command;
+pub mod record;
+pub mod text;
+pub mod file;
+pub mod fixed_step;
+pub mod frame;
+pub mod clock;
+pub mod stats;
+pub mod schedule;
+pub mod tween;
 
 pub mod utils {
     //!
@@ -63,6 +80,124 @@ pub mod utils {
     pub fn get_worker_global_context() -> web_sys::DedicatedWorkerGlobalScope {
         js_sys::global().dyn_into().unwrap_throw()
     }
+
+    ///
+    /// What the current javascript environment supports.
+    /// Fields that can't be determined from the calling context are `None`.
+    ///
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    pub struct Capabilities {
+        /// True if probed from inside a worker.
+        pub in_worker: bool,
+        /// `OffscreenCanvas` exists.
+        pub offscreen_canvas: bool,
+        /// `HTMLCanvasElement.transferControlToOffscreen` exists. Main thread only.
+        pub transfer_control_to_offscreen: Option<bool>,
+        /// Workers can be created with `type: "module"`. Main thread only.
+        pub module_workers: Option<bool>,
+        /// A webgl2 context can be created on an `OffscreenCanvas`.
+        pub webgl2_offscreen: bool,
+        /// `crossOriginIsolated` is true.
+        pub cross_origin_isolated: bool,
+        /// `SharedArrayBuffer` exists.
+        pub shared_array_buffer: bool,
+        /// `requestAnimationFrame` is available in the worker. Worker only.
+        pub worker_animation_frame: Option<bool>,
+        /// `window.devicePixelRatio`. Main thread only.
+        pub device_pixel_ratio: Option<f64>,
+    }
+
+    ///
+    /// Probe the current environment. Can be called from either the main thread or a worker.
+    ///
+    pub fn capabilities() -> Capabilities {
+        let global = js_sys::global();
+        let has = |obj: &JsValue, name: &str| {
+            js_sys::Reflect::has(obj, &JsValue::from_str(name)).unwrap_or(false)
+        };
+
+        let window = global.dyn_ref::<web_sys::Window>();
+        let in_worker = global.is_instance_of::<web_sys::DedicatedWorkerGlobalScope>();
+
+        let offscreen_canvas = has(&global, "OffscreenCanvas");
+
+        let transfer_control_to_offscreen = window.map(|_| {
+            js_sys::Reflect::get(&global, &JsValue::from_str("HTMLCanvasElement"))
+                .and_then(|c| js_sys::Reflect::get(&c, &JsValue::from_str("prototype")))
+                .map(|p| has(&p, "transferControlToOffscreen"))
+                .unwrap_or(false)
+        });
+
+        let module_workers = window.map(|_| probe_module_workers());
+
+        let webgl2_offscreen = offscreen_canvas
+            && web_sys::OffscreenCanvas::new(1, 1)
+                .ok()
+                .and_then(|c| c.get_context("webgl2").ok().flatten())
+                .map(|ctx| {
+                    //Browsers only allow a few live contexts, so give this one back.
+                    let ctx: web_sys::WebGl2RenderingContext = ctx.unchecked_into();
+                    if let Ok(Some(ext)) = ctx.get_extension("WEBGL_lose_context") {
+                        ext.unchecked_into::<web_sys::WebglLoseContext>()
+                            .lose_context();
+                    }
+                })
+                .is_some();
+
+        let cross_origin_isolated =
+            js_sys::Reflect::get(&global, &JsValue::from_str("crossOriginIsolated"))
+                .ok()
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
+        let shared_array_buffer = has(&global, "SharedArrayBuffer");
+
+        let worker_animation_frame = if in_worker {
+            Some(has(&global, "requestAnimationFrame"))
+        } else {
+            None
+        };
+
+        let device_pixel_ratio = window.map(|w| w.device_pixel_ratio());
+
+        Capabilities {
+            in_worker,
+            offscreen_canvas,
+            transfer_control_to_offscreen,
+            module_workers,
+            webgl2_offscreen,
+            cross_origin_isolated,
+            shared_array_buffer,
+            worker_animation_frame,
+            device_pixel_ratio,
+        }
+    }
+
+    //Browsers that support module workers read the `type` field of the options.
+    //Browsers that don't never touch it.
+    fn probe_module_workers() -> bool {
+        let supported = Rc::new(std::cell::Cell::new(false));
+
+        let s = supported.clone();
+        let getter = Closure::<dyn FnMut() -> JsValue>::new(move || {
+            s.set(true);
+            JsValue::from_str("module")
+        });
+
+        let desc = js_sys::Object::new();
+        js_sys::Reflect::set(&desc, &JsValue::from_str("get"), getter.as_ref()).unwrap_throw();
+        let options = js_sys::Object::define_property(
+            &js_sys::Object::new(),
+            &JsValue::from_str("type"),
+            &desc,
+        );
+
+        if let Ok(w) = web_sys::Worker::new_with_options("blob://", options.unchecked_ref()) {
+            w.terminate();
+        }
+
+        supported.get()
+    }
 }
 
 // #[wasm_bindgen]
@@ -73,97 +208,290 @@ pub mod utils {
 //     static performance: web_sys::Performance;
 // }
 
-pub struct Timer {
-    last: f64,
-    frame_rate: usize,
+///
+/// Waits between frames to keep to a frame rate, measuring time with a [`clock::Clock`].
+///
+pub struct Timer<C = clock::WorkerClock> {
+    clock: C,
+    //Milliseconds between frames.
+    period: f64,
+    //When the next frame is due.
+    due: f64,
+    last: Option<f64>,
+    index: u64,
 }
 impl Timer {
+    ///
+    /// A timer using the clock of the worker. Call from within a webworker.
+    ///
     pub fn new(frame_rate: usize) -> Timer {
-        let frame_rate = ((1.0 / frame_rate as f64) * 1000.0).round() as usize;
+        Timer::with_clock(frame_rate, clock::WorkerClock::new())
+    }
+}
+impl<C: clock::Clock> Timer<C> {
+    pub fn with_clock(frame_rate: usize, clock: C) -> Timer<C> {
+        assert!(frame_rate > 0);
+        let period = 1000.0 / frame_rate as f64;
+
+        Timer {
+            due: clock.now(),
+            clock,
+            period,
+            last: None,
+            index: 0,
+        }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
 
+    ///
+    /// Milliseconds between frames.
+    ///
+    pub fn period(&self) -> f64 {
+        self.period
+    }
+
+    ///
+    /// Change the frame rate. The next frame is due one new period after the last one,
+    /// or right away if that has already passed.
+    ///
+    pub fn set_frame_rate(&mut self, frame_rate: usize) {
         assert!(frame_rate > 0);
-        //let window = gloo::utils::window();
-        let performance = utils::get_worker_global_context()
-            .performance()
-            .unwrap_throw();
+        let period = 1000.0 / frame_rate as f64;
+        if period == self.period {
+            return;
+        }
+        self.period = period;
+        if let Some(last) = self.last {
+            self.due = (last + period).max(self.clock.now());
+        }
+    }
 
-        //let performance = window.performance().unwrap_throw();
+    ///
+    /// Wait until the next frame is due. If frames were missed because the last one ran long,
+    /// they are skipped rather than run back to back, so the timer stays on schedule.
+    ///
+    pub async fn next(&mut self) -> frame::FrameInfo {
+        let wait = self.due - self.clock.now();
+        if wait > 0.0 {
+            self.clock.sleep(wait).await;
+        }
 
-        Timer {
-            last: performance.now(),
-            frame_rate,
+        let time = self.clock.now();
+        let lateness = (time - self.due).max(0.0);
+        let skipped = (lateness / self.period) as u32;
+        self.due += self.period * (skipped + 1) as f64;
+
+        let info = frame::FrameInfo {
+            index: self.index,
+            time,
+            delta: self.last.map(|l| time - l).unwrap_or(0.0),
+            lateness,
+            skipped,
+        };
+        self.last = Some(time);
+        self.index += 1;
+        info
+    }
+}
+
+///
+/// Takes a stream, and continually returns a list of its items that have accumulated
+/// since the last frame. Frames come from any [`frame::FrameSource`].
+///
+/// ```ignore
+/// let (canvas, sender, recv) = shogo::worker::create_worker().await;
+/// let mut frame_timer = shogo::FrameTimer::with_source(frame::AutoFrameSource::new(60), recv);
+/// loop {
+///     let frame = frame_timer.next().await;
+///     for e in frame.events() {
+///         //...
+///     }
+/// }
+/// ```
+///
+pub struct FrameTimer<T, K, S = Timer> {
+    source: S,
+    buffer: Vec<T>,
+    stream: K,
+    done: bool,
+    is_input: Option<InputFilter<T>>,
+}
+
+type InputFilter<T> = Box<dyn FnMut(&T) -> bool>;
+
+impl<T, K: frame::FrameStream<Item = T>> FrameTimer<T, K> {
+    pub fn new(frame_rate: usize, stream: K) -> Self {
+        Self::with_source(Timer::new(frame_rate), stream)
+    }
+}
+impl<T, K: frame::FrameStream<Item = T>, S: frame::FrameSource> FrameTimer<T, K, S> {
+    pub fn with_source(source: S, stream: K) -> Self {
+        FrameTimer {
+            source,
+            buffer: vec![],
+            stream,
+            done: false,
+            is_input: None,
         }
     }
 
-    pub async fn next(&mut self) {
-        //let window = gloo::utils::window();
-        //let performance = window.performance().unwrap_throw();
-        let performance = utils::get_worker_global_context()
-            .performance()
-            .unwrap_throw();
+    ///
+    /// Only items the function returns true for are passed on to the source as
+    /// [`frame::FrameSource::message_arrived`], e.g. so a message that the page was hidden
+    /// doesn't wake an [`frame::IdleTimer`]. By default every item counts.
+    ///
+    pub fn with_input_filter(mut self, func: impl FnMut(&T) -> bool + 'static) -> Self {
+        let func: InputFilter<T> = Box::new(func);
+        self.is_input = Some(func);
+        self
+    }
+
+    pub fn source(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    pub fn stream(&mut self) -> &mut K {
+        &mut self.stream
+    }
 
-        let tt = performance.now();
-        let diff = performance.now() - self.last;
+    ///
+    /// Wait for the next frame, collecting the items that arrive in the meantime.
+    /// The buffer they are collected in is reused between frames.
+    ///
+    pub async fn next(&mut self) -> FrameEvents<'_, T> {
+        use futures::{FutureExt, StreamExt};
+
+        self.buffer.clear();
+
+        let info = loop {
+            let restart = self.source.restart_on_message();
+            let mut input = false;
+
+            let info = {
+                let tick = self.source.next_frame().fuse();
+                futures::pin_mut!(tick);
+
+                loop {
+                    if self.done {
+                        break Some(tick.await);
+                    }
+                    futures::select_biased!(
+                        info = tick => break Some(info),
+                        val = self.stream.next().fuse() => match val {
+                            Some(val) => {
+                                let is_input = self.is_input.as_mut().is_none_or(|f| f(&val));
+                                self.buffer.push(val);
+                                if is_input {
+                                    input = true;
+                                    if restart {
+                                        break None;
+                                    }
+                                }
+                            }
+                            None => self.done = true,
+                        }
+                    )
+                }
+            };
+
+            if input {
+                self.source.message_arrived();
+            }
+            if let Some(info) = info {
+                break info;
+            }
+        };
 
-        if self.frame_rate as f64 - diff > 0.0 {
-            let d = (self.frame_rate as f64 - diff) as usize;
-            TimeoutFuture::new(d.try_into().unwrap_throw()).await;
+        //Pick up anything queued when the frame started, e.g. replayed messages.
+        self.stream.start_frame(&info);
+        let mut input = false;
+        while let Some(Some(val)) = self.stream.next().now_or_never() {
+            input |= self.is_input.as_mut().is_none_or(|f| f(&val));
+            self.buffer.push(val);
+        }
+        if input {
+            self.source.message_arrived();
         }
 
-        self.last = tt;
+        FrameEvents {
+            info,
+            inner: &mut self.buffer,
+        }
     }
 }
 
-// ///
-// /// Takes a stream, and continually returns a list of its items that have accumulated over
-// /// the specified period.
-// ///
-// pub struct FrameTimer<T, K> {
-//     timer: Timer,
-//     buffer: Vec<T>,
-//     stream: K,
-// }
-// impl<T, K: Stream<Item = T> + std::marker::Unpin> FrameTimer<T, K> {
-//     pub fn new(frame_rate: usize, stream: K) -> Self {
-//         FrameTimer {
-//             timer: Timer::new(frame_rate),
-//             buffer: vec![],
-//             stream,
-//         }
-//     }
-//     pub async fn next(&mut self) -> Doop<T> {
-//         //self.buffer.clear();
-
-//         loop {
-//             futures::select_biased!(
-//                 _ = self.timer.next().fuse() =>{
-//                     break;
-//                 },
-//                 val = self.stream.next().fuse()=>{
-//                     self.buffer.push(val.unwrap_throw());
-//                 }
-//             )
-//         }
-//         Doop{inner:&mut self.buffer}
-//     }
-// }
-// pub struct Doop<'a,T>{
-//     inner:&'a mut Vec<T>
-// }
-// impl<'a,T> Doop<'a,T>{
-//     pub fn events(&self)->&[T]{
-//         &self.inner
-//     }
-// }
-// impl<'a,T> Drop for Doop<'a,T>{
-//     fn drop(&mut self) {
-//         self.inner.clear();
-//     }
-// }
+///
+/// The items that arrived before a frame, returned by [`FrameTimer::next`].
+///
+pub struct FrameEvents<'a, T> {
+    info: frame::FrameInfo,
+    inner: &'a mut Vec<T>,
+}
+impl<'a, T> FrameEvents<'a, T> {
+    pub fn info(&self) -> &frame::FrameInfo {
+        &self.info
+    }
+
+    pub fn events(&self) -> &[T] {
+        self.inner
+    }
+
+    ///
+    /// Take the items out of the buffer.
+    ///
+    pub fn drain(self) -> std::vec::Drain<'a, T> {
+        self.inner.drain(..)
+    }
+}
+impl<'a, T> IntoIterator for FrameEvents<'a, T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        let inner: &'a Vec<T> = self.inner;
+        inner.iter()
+    }
+}
 
 use gloop::Listen;
 //pub use main::EngineMain;
 use std::marker::PhantomData;
+
+//Messages handled by the library itself rather than passed on to the user.
+//They go in the third slot of a message, with an optional buffer in the fourth.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) enum ToMain {
+    Command(command::CommandRequest),
+    Download { filename: String, mime: String },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) enum ToWorker {
+    CommandResponse(command::CommandResponse),
+    Replay,
+    File { id: u32 },
+}
+
+//Returns the message and the list of objects to transfer with it.
+pub(crate) fn internal_message<T: Serialize>(
+    val: &T,
+    buffer: Option<&js_sys::ArrayBuffer>,
+) -> (js_sys::Array, js_sys::Array) {
+    let data = js_sys::Array::new();
+    data.set(0, JsValue::null());
+    data.set(1, JsValue::null());
+    data.set(2, JsValue::from_serde(val).unwrap_throw());
+
+    let transfer = js_sys::Array::new();
+    if let Some(b) = buffer {
+        data.set(3, b.into());
+        transfer.push(b);
+    }
+    (data, transfer)
+}
+
 pub mod main {
     use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender};
 
@@ -172,6 +500,8 @@ pub mod main {
     pub struct MyListen<WM> {
         ks: UnboundedSender<WM>,
         fs: Option<futures::channel::oneshot::Sender<()>>,
+        worker: Rc<RefCell<web_sys::Worker>>,
+        commands: Rc<RefCell<Option<command::CommandHandler>>>,
     }
 
     impl<WM: for<'a> Deserialize<'a>> Listen for MyListen<WM> {
@@ -182,6 +512,7 @@ pub mod main {
             let data: js_sys::Array = data.dyn_into().unwrap_throw();
             let m = data.get(0);
             let k = data.get(1);
+            let c = data.get(2);
 
             if !m.is_null() {
                 if let Some(s) = m.as_string() {
@@ -191,6 +522,15 @@ pub mod main {
                         }
                     }
                 }
+            } else if !c.is_undefined() && !c.is_null() {
+                match c.into_serde().unwrap_throw() {
+                    ToMain::Command(request) => {
+                        command::handle_request(&self.commands, &self.worker, request)
+                    }
+                    ToMain::Download { filename, mime } => {
+                        record::save_file(&filename, &mime, &data.get(3))
+                    }
+                }
             } else {
                 let a = k.into_serde().unwrap_throw();
                 self.ks.unbounded_send(a).unwrap_throw();
@@ -201,6 +541,7 @@ pub mod main {
     pub struct MainReceiver<WM> {
         _handle: gloop::EventListen<MyListen<WM>>,
         recv: futures::channel::mpsc::UnboundedReceiver<WM>,
+        pub(crate) commands: Rc<RefCell<Option<command::CommandHandler>>>,
     }
     impl<WM> MainReceiver<WM> {
         pub fn recv(&mut self) -> &mut futures::channel::mpsc::UnboundedReceiver<WM> {
@@ -208,8 +549,18 @@ pub mod main {
         }
     }
 
+    pub(crate) fn post_to_worker<MW: Serialize>(worker: &web_sys::Worker, val: &MW) {
+        let a = JsValue::from_serde(val).unwrap_throw();
+
+        let data = js_sys::Array::new();
+        data.set(0, JsValue::null());
+        data.set(1, a);
+
+        worker.post_message(&data).unwrap_throw();
+    }
+
     pub struct MainSender<MW> {
-        worker: std::rc::Rc<std::cell::RefCell<web_sys::Worker>>,
+        pub(crate) worker: std::rc::Rc<std::cell::RefCell<web_sys::Worker>>,
         _p: PhantomData<MW>,
     }
     impl<MW: Serialize> MainSender<MW> {
@@ -222,6 +573,91 @@ pub mod main {
 
             self.worker.borrow().post_message(&data).unwrap_throw();
         }
+
+        ///
+        /// Listen to events of the specified type on the element. Each event is mapped
+        /// through the function and if it returns a value, it is posted to the worker.
+        /// The listener is removed when the returned handle is dropped.
+        ///
+        pub fn register_event<F: FnMut(EventData) -> Option<MW> + 'static>(
+            &self,
+            elem: &web_sys::EventTarget,
+            event_type: &'static str,
+            func: F,
+        ) -> gloop::EventListen<MyListen2<F>> {
+            let l = self.listen2(elem, event_type, EventOptions::default(), func);
+            gloop::EventListen::new(elem, event_type, l)
+        }
+
+        ///
+        /// Same as [`MainSender::register_event`] but with options.
+        ///
+        pub fn register_event_with_options<F: FnMut(EventData) -> Option<MW> + 'static>(
+            &self,
+            elem: &web_sys::EventTarget,
+            event_type: &'static str,
+            options: EventOptions,
+            func: F,
+        ) -> EventListenWithOptions<MyListen2<F>> {
+            let l = self.listen2(elem, event_type, options, func);
+            EventListenWithOptions::new(elem, event_type, options, l)
+        }
+
+        fn listen2<F>(
+            &self,
+            elem: &web_sys::EventTarget,
+            event_type: &'static str,
+            options: EventOptions,
+            func: F,
+        ) -> MyListen2<F> {
+            MyListen2 {
+                func,
+                e: elem.clone(),
+                event_type,
+                options,
+                w: self.worker.clone(),
+            }
+        }
+
+        ///
+        /// Forward `pointerdown`, `pointermove`, `pointerup` and `pointercancel` events on the element
+        /// as [`input::InputEvent`]s mapped through the function.
+        ///
+        /// The pointer is captured on `pointerdown` so the release is seen even if it happens
+        /// outside of the element. Coalesced `pointermove` events are each forwarded in order.
+        /// Consider setting `touch-action: none` on the element so touches aren't turned into scrolls.
+        ///
+        pub fn register_pointer_events<F: FnMut(input::InputEvent) -> Option<MW> + 'static>(
+            &self,
+            elem: &web_sys::EventTarget,
+            options: EventOptions,
+            func: F,
+        ) -> PointerListen<F> {
+            let func = Rc::new(RefCell::new(func));
+
+            let handles = ["pointerdown", "pointermove", "pointerup", "pointercancel"]
+                .into_iter()
+                .map(|event_type| {
+                    let l = MyListenPointer {
+                        func: func.clone(),
+                        e: elem.clone(),
+                        options,
+                        w: self.worker.clone(),
+                    };
+                    EventListenWithOptions::new(elem, event_type, options, l)
+                })
+                .collect();
+
+            PointerListen { _handles: handles }
+        }
+    }
+
+    ///
+    /// Handle returned by [`MainSender::register_pointer_events`].
+    /// The listeners are removed when dropped.
+    ///
+    pub struct PointerListen<F> {
+        _handles: Vec<EventListenWithOptions<MyListenPointer<F>>>,
     }
 
     pub trait Transferable : Clone+Into<JsValue>+wasm_bindgen::JsCast+ std::fmt::Debug{
@@ -247,7 +683,14 @@ pub mod main {
         let ks: UnboundedSender<WM> = ks;
         let kr: UnboundedReceiver<WM> = kr;
 
-        let ml = MyListen { ks, fs };
+        let commands = Rc::new(RefCell::new(None));
+
+        let ml = MyListen {
+            ks,
+            fs,
+            worker: worker.clone(),
+            commands: commands.clone(),
+        };
 
         let _handle = gloop::EventListen::new(&worker.borrow(), "message", ml);
 
@@ -270,7 +713,11 @@ pub mod main {
                 worker,
                 _p: PhantomData,
             },
-            MainReceiver { _handle, recv: kr },
+            MainReceiver {
+                _handle,
+                recv: kr,
+                commands,
+            },
         )
     }
 
@@ -353,6 +800,7 @@ pub struct MyListen2<F> {
     func: F,
     e: web_sys::EventTarget,
     event_type: &'static str,
+    options: EventOptions,
     //TODO dont use reference counting
     w: Rc<RefCell<web_sys::Worker>>,
 }
@@ -362,6 +810,7 @@ impl<'a, MW: Serialize, F: FnMut(EventData) -> Option<MW>> gloop::Listen for MyL
             elem: &self.e,
             event,
             event_type: self.event_type,
+            coords: self.options.coords,
         };
 
         if let Some(val) = (self.func)(e) {
@@ -376,6 +825,37 @@ impl<'a, MW: Serialize, F: FnMut(EventData) -> Option<MW>> gloop::Listen for MyL
     }
 }
 
+pub struct MyListenPointer<F> {
+    func: Rc<RefCell<F>>,
+    e: web_sys::EventTarget,
+    options: EventOptions,
+    w: Rc<RefCell<web_sys::Worker>>,
+}
+impl<MW: Serialize, F: FnMut(input::InputEvent) -> Option<MW>> gloop::Listen
+    for MyListenPointer<F>
+{
+    fn call(&mut self, event: &web_sys::Event) {
+        let event = event.dyn_ref::<web_sys::PointerEvent>().unwrap_throw();
+        let elem = self.e.dyn_ref::<web_sys::Element>();
+
+        if event.type_() == "pointerdown" {
+            if let Some(elem) = elem {
+                let _ = elem.set_pointer_capture(event.pointer_id());
+            }
+        }
+
+        for mut e in input::InputEvent::from_pointer_event_coalesced(event) {
+            if let (Some(backing), Some(elem)) = (self.options.coords, elem) {
+                e.map_pos(|p| input::convert_coord(elem, p, backing));
+            }
+
+            if let Some(val) = (self.func.borrow_mut())(e) {
+                main::post_to_worker(&self.w.borrow(), &val);
+            }
+        }
+    }
+}
+
 ///
 /// Data that can be accessed when handling events in the main thread to help
 /// construct the data to be passed to the worker thread.
@@ -384,6 +864,129 @@ pub struct EventData<'a> {
     pub elem: &'a web_sys::EventTarget,
     pub event: &'a web_sys::Event,
     pub event_type: &'static str,
+    pub coords: Option<input::Backing>,
+}
+impl<'a> EventData<'a> {
+    ///
+    /// Convert the event into an [`input::InputEvent`]. If the event was registered with
+    /// [`EventOptions::coords`], positions are converted into backing store coordinates
+    /// of the element.
+    ///
+    pub fn input(&self) -> Option<input::InputEvent> {
+        let mut e = input::InputEvent::from_event(self.event)?;
+        if let (Some(backing), Some(elem)) = (self.coords, self.elem.dyn_ref::<web_sys::Element>())
+        {
+            e.map_pos(|p| input::convert_coord(elem, p, backing));
+        }
+        Some(e)
+    }
+}
+
+///
+/// Options for [`main::MainSender::register_event_with_options`].
+///
+/// ```ignore
+/// //Stop the context menu from opening on right click.
+/// let _handle = sender.register_event_with_options(
+///     &canvas,
+///     "contextmenu",
+///     EventOptions {
+///         prevent_default: true,
+///         ..Default::default()
+///     },
+///     |_| None,
+/// );
+/// ```
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EventOptions {
+    ///
+    /// If set, [`EventData::input`] converts positions into the
+    /// backing store coordinates of the element.
+    ///
+    pub coords: Option<input::Backing>,
+    ///
+    /// Tell the browser the listener never calls `preventDefault`, so scrolling isn't blocked
+    /// waiting on it. Ignored if `prevent_default` is set, since the browser would
+    /// ignore the `preventDefault` calls of a passive listener.
+    ///
+    pub passive: bool,
+    ///
+    /// Listen during the capture phase.
+    ///
+    pub capture: bool,
+    ///
+    /// Remove the listener after the first event.
+    ///
+    pub once: bool,
+    ///
+    /// Call `preventDefault` on every event, e.g. to stop page scrolling on touch drag
+    /// or browser zoom on ctrl+wheel.
+    ///
+    pub prevent_default: bool,
+    ///
+    /// Call `stopPropagation` on every event.
+    ///
+    pub stop_propagation: bool,
+}
+
+///
+/// Like [`gloop::EventListen`] but registered with [`EventOptions`].
+/// The listener is removed when dropped.
+///
+pub struct EventListenWithOptions<T> {
+    target: web_sys::EventTarget,
+    event_type: &'static str,
+    capture: bool,
+    callback: Closure<dyn FnMut(web_sys::Event)>,
+    _p: PhantomData<T>,
+}
+impl<T: gloop::Listen + 'static> EventListenWithOptions<T> {
+    pub fn new(
+        elem: &web_sys::EventTarget,
+        event_type: &'static str,
+        options: EventOptions,
+        mut listen: T,
+    ) -> Self {
+        let callback = Closure::<dyn FnMut(web_sys::Event)>::new(move |e: web_sys::Event| {
+            if options.prevent_default {
+                e.prevent_default();
+            }
+            if options.stop_propagation {
+                e.stop_propagation();
+            }
+            listen.call(&e);
+        });
+
+        let opts = web_sys::AddEventListenerOptions::new();
+        opts.set_passive(options.passive && !options.prevent_default);
+        opts.set_capture(options.capture);
+        opts.set_once(options.once);
+
+        elem.add_event_listener_with_callback_and_add_event_listener_options(
+            event_type,
+            callback.as_ref().unchecked_ref(),
+            &opts,
+        )
+        .unwrap_throw();
+
+        EventListenWithOptions {
+            target: elem.clone(),
+            event_type,
+            capture: options.capture,
+            callback,
+            _p: PhantomData,
+        }
+    }
+}
+impl<T> Drop for EventListenWithOptions<T> {
+    fn drop(&mut self) {
+        let _ = self.target.remove_event_listener_with_callback_and_bool(
+            self.event_type,
+            self.callback.as_ref().unchecked_ref(),
+            self.capture,
+        );
+    }
 }
 
 //pub use worker::EngineWorker;
@@ -402,6 +1005,7 @@ pub mod worker {
     // }
 
     pub struct WorkerSender<WM> {
+        pub(crate) commands: Rc<RefCell<command::Pending>>,
         _p: PhantomData<WM>,
     }
     impl<WM: Serialize> WorkerSender<WM> {
@@ -420,6 +1024,9 @@ pub mod worker {
         _handle: gloop::EventListen<MyListen3<MW,T>>,
         //canvas: web_sys::OffscreenCanvas,
         recv: futures::channel::mpsc::UnboundedReceiver<MW>,
+        pub(crate) inject: futures::channel::mpsc::UnboundedSender<MW>,
+        pub(crate) record: Rc<RefCell<record::RecordState<MW>>>,
+        pub(crate) files: Rc<RefCell<std::collections::HashMap<u32, js_sys::ArrayBuffer>>>,
     }
     impl<MW,T:Transferable> WorkerRecv<MW,T> {
         pub fn recv(&mut self) -> &mut futures::channel::mpsc::UnboundedReceiver<MW> {
@@ -438,7 +1045,17 @@ pub mod worker {
         let (bags, bagf): (futures::channel::mpsc::UnboundedSender<MW>, _) =
             futures::channel::mpsc::unbounded();
 
-        let fff = MyListen3 { fs, bags };
+        let commands = Rc::new(RefCell::new(command::Pending::default()));
+        let record = Rc::new(RefCell::new(record::RecordState::default()));
+        let files = Rc::new(RefCell::new(std::collections::HashMap::new()));
+
+        let fff = MyListen3 {
+            fs,
+            bags: bags.clone(),
+            commands: commands.clone(),
+            record: record.clone(),
+            files: files.clone(),
+        };
 
         let _handle = gloop::EventListen::new(&scope, "message", fff);
 
@@ -452,10 +1069,16 @@ pub mod worker {
 
         (
             canvas,
-            WorkerSender { _p: PhantomData },
+            WorkerSender {
+                commands,
+                _p: PhantomData,
+            },
             WorkerRecv {
                 _handle,
                 recv: bagf,
+                inject: bags,
+                record,
+                files,
             },
         )
     }
@@ -524,6 +1147,9 @@ pub mod worker {
 pub struct MyListen3<MW,T:main::Transferable> {
     fs: Option<futures::channel::oneshot::Sender<T>>,
     bags: futures::channel::mpsc::UnboundedSender<MW>,
+    commands: Rc<RefCell<command::Pending>>,
+    record: Rc<RefCell<record::RecordState<MW>>>,
+    files: Rc<RefCell<std::collections::HashMap<u32, js_sys::ArrayBuffer>>>,
 }
 
 impl<MW: for<'a> Deserialize<'a>,T:Transferable> gloop::Listen for MyListen3<MW,T> {
@@ -542,9 +1168,122 @@ impl<MW: for<'a> Deserialize<'a>,T:Transferable> gloop::Listen for MyListen3<MW,
             }
         }
 
-        if !payload.is_null() {
+        let deliver = !payload.is_null() && self.record.borrow_mut().live(&payload);
+
+        //Handled first so a file is stored before the message announcing it is received.
+        let internal = data.get(2);
+        if !internal.is_undefined() && !internal.is_null() {
+            match internal.into_serde().unwrap_throw() {
+                ToWorker::CommandResponse(r) => self.commands.borrow_mut().respond(r),
+                ToWorker::Replay => self.record.borrow_mut().load(&data.get(3)),
+                //If the message announcing the file is dropped during a replay,
+                //nothing would ever take the file.
+                ToWorker::File { id } => {
+                    if deliver {
+                        self.files.borrow_mut().insert(id, data.get(3).unchecked_into());
+                    }
+                }
+            }
+        }
+
+        if deliver {
             let e = payload.into_serde().unwrap_throw();
             self.bags.unbounded_send(e).unwrap_throw();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::Clock;
+    use futures::channel::mpsc;
+    use futures::executor::block_on;
+
+    fn timer() -> (clock::MockClock, Timer<clock::MockClock>) {
+        let clock = clock::MockClock::new();
+        (clock.clone(), Timer::with_clock(60, clock))
+    }
+
+    //Remembers the frames it was told about and queues a message on each, like a replay.
+    struct Stream {
+        recv: mpsc::UnboundedReceiver<u32>,
+        inject: mpsc::UnboundedSender<u32>,
+        frames: Vec<u64>,
+    }
+    impl futures::Stream for Stream {
+        type Item = u32;
+        fn poll_next(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Option<u32>> {
+            std::pin::Pin::new(&mut self.recv).poll_next(cx)
+        }
+    }
+    impl frame::FrameStream for Stream {
+        fn start_frame(&mut self, info: &frame::FrameInfo) {
+            self.frames.push(info.index);
+            self.inject.unbounded_send(100 + info.index as u32).unwrap();
+        }
+    }
+
+    #[test]
+    fn batches_messages() {
+        let (_, timer) = timer();
+        let (send, recv) = mpsc::unbounded();
+        let mut frames = FrameTimer::with_source(timer, recv);
+
+        send.unbounded_send(1).unwrap();
+        send.unbounded_send(2).unwrap();
+        let frame = block_on(frames.next());
+        assert_eq!(frame.info().index, 0);
+        assert_eq!(frame.events(), &[1, 2]);
+        let first = frame.events().as_ptr();
+
+        assert!(block_on(frames.next()).events().is_empty());
+
+        //The same buffer is reused.
+        send.unbounded_send(3).unwrap();
+        let frame = block_on(frames.next());
+        assert_eq!(frame.info().index, 2);
+        assert_eq!(frame.events(), &[3]);
+        assert_eq!(frame.events().as_ptr(), first);
+        assert_eq!(frame.drain().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn start_frame_gets_index() {
+        let (_, timer) = timer();
+        let (send, recv) = mpsc::unbounded();
+        let stream = Stream {
+            recv,
+            inject: send.clone(),
+            frames: vec![],
+        };
+        let mut frames = FrameTimer::with_source(timer, stream);
+
+        send.unbounded_send(1).unwrap();
+        assert_eq!(block_on(frames.next()).events(), &[1, 100]);
+        assert_eq!(block_on(frames.next()).events(), &[101]);
+        assert_eq!(frames.stream().frames, vec![0, 1]);
+    }
+
+    #[test]
+    fn stream_end() {
+        let (clock, timer) = timer();
+        let (send, recv) = mpsc::unbounded();
+        let mut frames = FrameTimer::with_source(timer, recv);
+
+        send.unbounded_send(1).unwrap();
+        drop(send);
+        assert_eq!(block_on(frames.next()).events(), &[1]);
+
+        //Frames keep coming on schedule after the stream ends.
+        for index in 1..4 {
+            let frame = block_on(frames.next());
+            assert_eq!(frame.info().index, index);
+            assert!(frame.events().is_empty());
+        }
+        assert_eq!(clock.now(), 3.0 * 1000.0 / 60.0);
+    }
+}
@@ -0,0 +1,223 @@
+//!
+//! Page visibility, focus and lifecycle events forwarded to the worker, so it can
+//! stop rendering while the page is hidden.
+//!
+//! Main thread:
+//! ```ignore
+//! let _lifecycle = sender.forward_lifecycle(|e| Some(MEvent::Lifecycle(e)));
+//! ```
+//!
+//! Worker:
+//! ```ignore
+//! let mut lifecycle = LifecycleState::new();
+//! let mut events = vec![];
+//! loop {
+//!     lifecycle::wait_while_paused(&mut lifecycle, frame_timer.stream(), &mut events, |e| match e {
+//!         MEvent::Lifecycle(e) => Some(*e),
+//!         _ => None,
+//!     })
+//!     .await;
+//!
+//!     for e in events.drain(..) {
+//!         match e {
+//!             MEvent::Lifecycle(e) => {
+//!                 lifecycle.handle(&e);
+//!                 input.handle_lifecycle(&e);
+//!             }
+//!             //...
+//!         }
+//!     }
+//!     //...
+//! }
+//! ```
+//!
+use super::*;
+use futures::StreamExt;
+
+///
+/// A change in the state of the page.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lifecycle {
+    ///
+    /// The page became visible. Sent from `visibilitychange`.
+    ///
+    Visible,
+    ///
+    /// The page became hidden, e.g. the tab was switched or the window minimized.
+    ///
+    Hidden,
+    Focus,
+    Blur,
+    ///
+    /// `persisted` is true if the page was restored from the back/forward cache.
+    ///
+    PageShow {
+        persisted: bool,
+    },
+    PageHide {
+        persisted: bool,
+    },
+}
+
+///
+/// Whether the page is visible and focused, built up from [`Lifecycle`] events.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LifecycleState {
+    pub visible: bool,
+    pub focused: bool,
+    pub shown: bool,
+    ///
+    /// If true, losing focus also pauses. Defaults to false.
+    ///
+    pub pause_on_blur: bool,
+}
+impl Default for LifecycleState {
+    fn default() -> Self {
+        LifecycleState {
+            visible: true,
+            focused: true,
+            shown: true,
+            pause_on_blur: false,
+        }
+    }
+}
+impl LifecycleState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle(&mut self, event: &Lifecycle) {
+        match *event {
+            Lifecycle::Visible => self.visible = true,
+            Lifecycle::Hidden => self.visible = false,
+            Lifecycle::Focus => self.focused = true,
+            Lifecycle::Blur => self.focused = false,
+            Lifecycle::PageShow { .. } => self.shown = true,
+            Lifecycle::PageHide { .. } => self.shown = false,
+        }
+    }
+
+    ///
+    /// True if the worker loop should stop updating and rendering.
+    ///
+    pub fn paused(&self) -> bool {
+        !self.visible || !self.shown || (self.pause_on_blur && !self.focused)
+    }
+}
+
+///
+/// While paused, wait for messages instead of running the loop. Every message received
+/// is pushed onto `buffer` so none are lost. Lifecycle messages, as picked out by `func`,
+/// are applied to `state` as they arrive. Returns immediately if not paused.
+///
+/// `recv` can be a [`worker::WorkerRecv`], so replayed messages are seen too,
+/// or the stream of a [`FrameTimer`].
+///
+pub async fn wait_while_paused<MW>(
+    state: &mut LifecycleState,
+    recv: &mut (impl futures::Stream<Item = MW> + Unpin),
+    buffer: &mut Vec<MW>,
+    mut func: impl FnMut(&MW) -> Option<Lifecycle>,
+) {
+    while state.paused() {
+        let Some(e) = recv.next().await else {
+            return;
+        };
+        if let Some(l) = func(&e) {
+            state.handle(&l);
+        }
+        buffer.push(e);
+    }
+}
+
+pub struct MyListenLifecycle {
+    send: Rc<RefCell<dyn FnMut(Lifecycle)>>,
+}
+impl gloop::Listen for MyListenLifecycle {
+    fn call(&mut self, event: &web_sys::Event) {
+        let e = match event.type_().as_str() {
+            "visibilitychange" => {
+                if gloo::utils::document().hidden() {
+                    Lifecycle::Hidden
+                } else {
+                    Lifecycle::Visible
+                }
+            }
+            "focus" => Lifecycle::Focus,
+            "blur" => Lifecycle::Blur,
+            a @ ("pageshow" | "pagehide") => {
+                let persisted = event
+                    .dyn_ref::<web_sys::PageTransitionEvent>()
+                    .map(|e| e.persisted())
+                    .unwrap_or(false);
+                if a == "pageshow" {
+                    Lifecycle::PageShow { persisted }
+                } else {
+                    Lifecycle::PageHide { persisted }
+                }
+            }
+            _ => return,
+        };
+        (self.send.borrow_mut())(e);
+    }
+}
+
+///
+/// Handle returned by [`main::MainSender::forward_lifecycle`]. The listeners are removed when dropped.
+///
+pub struct LifecycleListen {
+    _handles: Vec<gloop::EventListen<MyListenLifecycle>>,
+}
+
+impl<MW: Serialize + 'static> main::MainSender<MW> {
+    ///
+    /// Forward `visibilitychange`, window `focus`/`blur` and `pageshow`/`pagehide`
+    /// as [`Lifecycle`] events mapped through the function.
+    /// The current visibility and focus are sent right away.
+    ///
+    pub fn forward_lifecycle<F: FnMut(Lifecycle) -> Option<MW> + 'static>(
+        &self,
+        mut func: F,
+    ) -> LifecycleListen {
+        let worker = self.worker.clone();
+        let send: Rc<RefCell<dyn FnMut(Lifecycle)>> = Rc::new(RefCell::new(move |e| {
+            if let Some(val) = func(e) {
+                main::post_to_worker(&worker.borrow(), &val);
+            }
+        }));
+
+        let document = gloo::utils::document();
+        let window = gloo::utils::window();
+
+        {
+            let mut send = send.borrow_mut();
+            send(if document.hidden() {
+                Lifecycle::Hidden
+            } else {
+                Lifecycle::Visible
+            });
+            send(if document.has_focus().unwrap_or(true) {
+                Lifecycle::Focus
+            } else {
+                Lifecycle::Blur
+            });
+        }
+
+        let mut handles = vec![gloop::EventListen::new(
+            &document,
+            "visibilitychange",
+            MyListenLifecycle { send: send.clone() },
+        )];
+        for event_type in ["focus", "blur", "pageshow", "pagehide"] {
+            handles.push(gloop::EventListen::new(
+                &window,
+                event_type,
+                MyListenLifecycle { send: send.clone() },
+            ));
+        }
+
+        LifecycleListen { _handles: handles }
+    }
+}
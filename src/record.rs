@@ -0,0 +1,625 @@
+//!
+//! Record the messages the worker receives and replay them later to reproduce bugs.
+//!
+//! Worker:
+//! ```ignore
+//! recv.start_recording();
+//! loop {
+//!     frame += 1;
+//!     recv.set_frame(frame);
+//!     //...
+//!     if save_requested {
+//!         let bytes = recv.stop_recording().unwrap_throw();
+//!         sender.download("session.shogorec", record::MIME, &bytes);
+//!     }
+//! }
+//!
+//! //Replay a recording from a url.
+//! let bytes = record::fetch("./session.shogorec").await.unwrap_throw();
+//! recv.start_replay(&bytes).unwrap_throw();
+//! ```
+//!
+//! Main thread:
+//! ```ignore
+//! //Replay recordings dropped onto the canvas.
+//! let _drop = sender.replay_on_drop(&canvas);
+//! ```
+//!
+//! A message is tagged with the frame that will receive it, which is the one after the
+//! last call to [`worker::WorkerRecv::set_frame`]. Frames are stored relative to when the
+//! recording started, so a replay plays back from whatever frame it is started on.
+//! [`FrameTimer`] sets the frame automatically when it batches a `WorkerRecv`.
+//!
+//! The format is the 8 bytes `SHOGOREC`, a little endian `u16` version and then one
+//! entry per message: the number of frames since the last entry and the length of the
+//! message as LEB128 varints, followed by the message as UTF-8 JSON.
+//!
+//! Only the messages are recorded. Anything transferred alongside them, like the
+//! contents of a [`file::FileEvent`], is not, so [`worker::WorkerRecv::take_file`]
+//! returns `None` during a replay.
+//!
+use super::*;
+use serde::de::DeserializeOwned;
+use std::collections::VecDeque;
+
+pub const MAGIC: &[u8; 8] = b"SHOGOREC";
+pub const VERSION: u16 = 1;
+
+///
+/// The MIME type to download recordings with.
+///
+pub const MIME: &str = "application/octet-stream";
+
+///
+/// Why a recording could not be loaded.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    ///
+    /// A frame number didn't fit in a `u64`, so the recording is corrupt.
+    ///
+    FrameOverflow,
+    ///
+    /// A message didn't match the message type, e.g. it was recorded by a different build.
+    ///
+    Message {
+        frame: u64,
+        error: String,
+    },
+    Fetch(String),
+}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordError::BadMagic => write!(f, "not a recording"),
+            RecordError::UnsupportedVersion(v) => {
+                write!(f, "unsupported recording version {}", v)
+            }
+            RecordError::Truncated => write!(f, "recording is truncated"),
+            RecordError::FrameOverflow => write!(f, "recording has an invalid frame number"),
+            RecordError::Message { frame, error } => {
+                write!(f, "bad message at frame {}: {}", frame, error)
+            }
+            RecordError::Fetch(e) => write!(f, "failed to fetch recording: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+fn write_varint(out: &mut Vec<u8>, mut v: u64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, RecordError> {
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().ok_or(RecordError::Truncated)?;
+        *bytes = rest;
+        v |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(v);
+        }
+    }
+    Err(RecordError::Truncated)
+}
+
+///
+/// Builds a recording one message at a time.
+///
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    start: u64,
+    last: u64,
+    len: usize,
+    bytes: Vec<u8>,
+}
+
+impl Recorder {
+    ///
+    /// Start a recording. Frames are stored relative to `start_frame`.
+    ///
+    pub fn new(start_frame: u64) -> Self {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        Recorder {
+            start: start_frame,
+            last: start_frame,
+            len: 0,
+            bytes,
+        }
+    }
+
+    pub fn record<MW: Serialize>(&mut self, frame: u64, msg: &MW) {
+        let json = serde_json::to_string(msg).unwrap_throw();
+        self.record_json(frame, &json);
+    }
+
+    ///
+    /// Record a message that is already serialized. Frames earlier than the
+    /// previous message are recorded as the same frame as it.
+    ///
+    pub fn record_json(&mut self, frame: u64, json: &str) {
+        let frame = frame.max(self.last);
+        write_varint(&mut self.bytes, frame - self.last);
+        write_varint(&mut self.bytes, json.len() as u64);
+        self.bytes.extend_from_slice(json.as_bytes());
+        self.last = frame;
+        self.len += 1;
+    }
+
+    ///
+    /// The frame the recording started on.
+    ///
+    pub fn start_frame(&self) -> u64 {
+        self.start
+    }
+
+    ///
+    /// The number of messages recorded.
+    ///
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+///
+/// A parsed recording. Each entry is a message and its frame relative to the start.
+/// File contents are not included, see the [module docs](self).
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording<MW> {
+    pub entries: Vec<(u64, MW)>,
+}
+
+impl<MW: DeserializeOwned> Recording<MW> {
+    pub fn parse(bytes: &[u8]) -> Result<Self, RecordError> {
+        let rest = bytes
+            .strip_prefix(&MAGIC[..])
+            .ok_or(RecordError::BadMagic)?;
+        let (version, mut rest) = match rest {
+            [a, b, rest @ ..] => (u16::from_le_bytes([*a, *b]), rest),
+            _ => return Err(RecordError::Truncated),
+        };
+        if version != VERSION {
+            return Err(RecordError::UnsupportedVersion(version));
+        }
+
+        let mut entries = vec![];
+        let mut frame = 0u64;
+        while !rest.is_empty() {
+            frame = frame
+                .checked_add(read_varint(&mut rest)?)
+                .ok_or(RecordError::FrameOverflow)?;
+            let len =
+                usize::try_from(read_varint(&mut rest)?).map_err(|_| RecordError::Truncated)?;
+            if rest.len() < len {
+                return Err(RecordError::Truncated);
+            }
+            let (json, r) = rest.split_at(len);
+            rest = r;
+
+            let msg = serde_json::from_slice(json).map_err(|e| RecordError::Message {
+                frame,
+                error: e.to_string(),
+            })?;
+            entries.push((frame, msg));
+        }
+        Ok(Recording { entries })
+    }
+}
+
+impl<MW: Serialize> Recording<MW> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut r = Recorder::new(0);
+        for (frame, msg) in self.entries.iter() {
+            r.record(*frame, msg);
+        }
+        r.into_bytes()
+    }
+}
+
+///
+/// Plays back a [`Recording`] starting from a given frame.
+///
+#[derive(Debug, Clone)]
+pub struct Replay<MW> {
+    start: u64,
+    entries: VecDeque<(u64, MW)>,
+}
+
+impl<MW> Replay<MW> {
+    pub fn new(recording: Recording<MW>, start_frame: u64) -> Self {
+        Replay {
+            start: start_frame,
+            entries: recording.entries.into(),
+        }
+    }
+
+    ///
+    /// Take the next message that is due on or before `frame`.
+    ///
+    pub fn next_due(&mut self, frame: u64) -> Option<MW> {
+        let (f, _) = self.entries.front()?;
+        //A due frame past u64::MAX is never reached.
+        if self.start.saturating_add(*f) <= frame {
+            self.entries.pop_front().map(|(_, msg)| msg)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+///
+/// Fetch a recording from a url. Works on the main thread and in workers.
+///
+pub async fn fetch(url: &str) -> Result<Vec<u8>, RecordError> {
+    let res = gloo::net::http::Request::get(url)
+        .send()
+        .await
+        .map_err(|e| RecordError::Fetch(e.to_string()))?;
+    if !res.ok() {
+        return Err(RecordError::Fetch(format!(
+            "{} {}",
+            res.status(),
+            res.status_text()
+        )));
+    }
+    res.binary()
+        .await
+        .map_err(|e| RecordError::Fetch(e.to_string()))
+}
+
+//Shared between the worker's message listener and its `WorkerRecv`.
+pub(crate) struct RecordState<MW> {
+    frame: u64,
+    recorder: Option<Recorder>,
+    replay: Option<Replay<MW>>,
+}
+
+impl<MW> Default for RecordState<MW> {
+    fn default() -> Self {
+        RecordState {
+            frame: 0,
+            recorder: None,
+            replay: None,
+        }
+    }
+}
+
+impl<MW> RecordState<MW> {
+    //Record a live message. Returns false if it should be dropped because a replay is running.
+    pub fn live(&mut self, payload: &JsValue) -> bool {
+        if let Some(r) = &mut self.recorder {
+            let json = String::from(js_sys::JSON::stringify(payload).unwrap_throw());
+            r.record_json(self.frame + 1, &json);
+        }
+        self.replay.is_none()
+    }
+}
+
+impl<MW: DeserializeOwned> RecordState<MW> {
+    pub fn start_replay(&mut self, bytes: &[u8]) -> Result<(), RecordError> {
+        let recording = Recording::parse(bytes)?;
+        self.replay = Some(Replay::new(recording, self.frame));
+        Ok(())
+    }
+
+    //Start replaying a recording dropped onto the main thread.
+    pub fn load(&mut self, buffer: &JsValue) {
+        let bytes = js_sys::Uint8Array::new(buffer).to_vec();
+        if let Err(e) = self.start_replay(&bytes) {
+            gloo::console::error!(format!("could not replay recording: {}", e));
+        }
+    }
+}
+
+impl<MW, T: main::Transferable> worker::WorkerRecv<MW, T> {
+    ///
+    /// Set the current frame of the worker loop. Call at the start of every frame,
+    /// before reading messages. While replaying, this queues the recorded messages
+    /// due up to this frame.
+    ///
+    pub fn set_frame(&mut self, frame: u64) {
+        let mut s = self.record.borrow_mut();
+        s.frame = frame;
+
+        if let Some(replay) = &mut s.replay {
+            while let Some(msg) = replay.next_due(frame) {
+                self.inject.unbounded_send(msg).unwrap_throw();
+            }
+            if replay.is_finished() {
+                s.replay = None;
+            }
+        }
+    }
+
+    ///
+    /// Start recording received messages, discarding any recording in progress.
+    ///
+    pub fn start_recording(&mut self) {
+        let mut s = self.record.borrow_mut();
+        s.recorder = Some(Recorder::new(s.frame));
+    }
+
+    ///
+    /// Stop recording and return the recording, if one was in progress.
+    ///
+    pub fn stop_recording(&mut self) -> Option<Vec<u8>> {
+        self.record
+            .borrow_mut()
+            .recorder
+            .take()
+            .map(|r| r.into_bytes())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.record.borrow().recorder.is_some()
+    }
+
+    ///
+    /// Stop delivering live messages and instead deliver the messages of the recording,
+    /// each on its recorded frame relative to the current frame. Live messages resume once
+    /// the replay finishes or is stopped.
+    ///
+    pub fn start_replay(&mut self, bytes: &[u8]) -> Result<(), RecordError>
+    where
+        MW: DeserializeOwned,
+    {
+        self.record.borrow_mut().start_replay(bytes)
+    }
+
+    pub fn stop_replay(&mut self) {
+        self.record.borrow_mut().replay = None;
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.record.borrow().replay.is_some()
+    }
+}
+
+impl<WM> worker::WorkerSender<WM> {
+    ///
+    /// Have the main thread save the bytes as a file download.
+    ///
+    pub fn download(&self, filename: &str, mime: &str, bytes: &[u8]) {
+        let buffer = js_sys::Uint8Array::from(bytes).buffer();
+        let msg = ToMain::Download {
+            filename: filename.to_string(),
+            mime: mime.to_string(),
+        };
+        let (data, transfer) = internal_message(&msg, Some(&buffer));
+        utils::get_worker_global_context()
+            .post_message_with_transfer(&data, &transfer)
+            .unwrap_throw();
+    }
+}
+
+pub(crate) fn save_file(filename: &str, mime: &str, buffer: &JsValue) {
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type(mime);
+    let blob = web_sys::Blob::new_with_buffer_source_sequence_and_options(
+        &js_sys::Array::of1(buffer),
+        &options,
+    )
+    .unwrap_throw();
+    let url = web_sys::Url::create_object_url_with_blob(&blob).unwrap_throw();
+
+    let a: web_sys::HtmlAnchorElement = gloo::utils::document()
+        .create_element("a")
+        .unwrap_throw()
+        .dyn_into()
+        .unwrap_throw();
+    a.set_href(&url);
+    a.set_download(filename);
+    a.click();
+
+    //Give the download a chance to start before the url is released.
+    gloo::timers::callback::Timeout::new(0, move || {
+        let _ = web_sys::Url::revoke_object_url(&url);
+    })
+    .forget();
+}
+
+pub struct MyListenReplayDrop {
+    w: Rc<RefCell<web_sys::Worker>>,
+}
+impl gloop::Listen for MyListenReplayDrop {
+    fn call(&mut self, event: &web_sys::Event) {
+        if event.type_() != "drop" {
+            return;
+        }
+        let Some(file) = event
+            .dyn_ref::<web_sys::DragEvent>()
+            .and_then(|e| e.data_transfer())
+            .and_then(|d| d.files())
+            .and_then(|f| f.get(0))
+        else {
+            return;
+        };
+
+        let w = self.w.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let Ok(buffer) = wasm_bindgen_futures::JsFuture::from(file.array_buffer()).await else {
+                return;
+            };
+            let buffer: js_sys::ArrayBuffer = buffer.unchecked_into();
+            //Leave other files alone, e.g. for a file drop listener on the same element.
+            let len = buffer.byte_length().min(MAGIC.len() as u32);
+            let head = js_sys::Uint8Array::new_with_byte_offset_and_length(&buffer, 0, len);
+            if head.to_vec() != MAGIC[..] {
+                return;
+            }
+            let (data, transfer) = internal_message(&ToWorker::Replay, Some(&buffer));
+            w.borrow()
+                .post_message_with_transfer(&data, &transfer)
+                .unwrap_throw();
+        });
+    }
+}
+
+///
+/// Handle returned by [`main::MainSender::replay_on_drop`]. The listeners are removed when dropped.
+///
+pub struct ReplayDrop {
+    _dragover: EventListenWithOptions<MyListenReplayDrop>,
+    _drop: EventListenWithOptions<MyListenReplayDrop>,
+}
+
+impl<MW> main::MainSender<MW> {
+    ///
+    /// Replay recordings dropped onto the element in the worker.
+    /// Dropped files that aren't recordings are ignored.
+    ///
+    pub fn replay_on_drop(&self, elem: &web_sys::EventTarget) -> ReplayDrop {
+        let options = EventOptions {
+            prevent_default: true,
+            ..Default::default()
+        };
+        //dragover only needs its default prevented to allow dropping.
+        let listen = || MyListenReplayDrop {
+            w: self.worker.clone(),
+        };
+        ReplayDrop {
+            _dragover: EventListenWithOptions::new(elem, "dragover", options, listen()),
+            _drop: EventListenWithOptions::new(elem, "drop", options, listen()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording() -> Recording<String> {
+        let mut r = Recorder::new(10);
+        r.record(11, &"a".to_owned());
+        r.record(11, &"b".to_owned());
+        r.record(15, &"c".to_owned());
+        //Earlier frames are recorded as the last one.
+        r.record(12, &"d".to_owned());
+        assert_eq!(r.len(), 4);
+        Recording::parse(r.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let rec = recording();
+        assert_eq!(
+            rec.entries,
+            vec![
+                (1, "a".to_owned()),
+                (1, "b".to_owned()),
+                (5, "c".to_owned()),
+                (5, "d".to_owned()),
+            ]
+        );
+        let again: Recording<String> = Recording::parse(&rec.to_bytes()).unwrap();
+        assert_eq!(again, rec);
+    }
+
+    #[test]
+    fn replay_from_start_frame() {
+        let mut replay = Replay::new(recording(), 100);
+        assert_eq!(replay.next_due(100), None);
+        assert_eq!(replay.next_due(101).as_deref(), Some("a"));
+        assert_eq!(replay.next_due(101).as_deref(), Some("b"));
+        assert_eq!(replay.next_due(101), None);
+        assert_eq!(replay.next_due(200).as_deref(), Some("c"));
+        assert_eq!(replay.next_due(200).as_deref(), Some("d"));
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = recording().to_bytes();
+        bytes[0] = b'X';
+        assert_eq!(
+            Recording::<String>::parse(&bytes),
+            Err(RecordError::BadMagic)
+        );
+        assert_eq!(Recording::<String>::parse(b""), Err(RecordError::BadMagic));
+    }
+
+    #[test]
+    fn wrong_version() {
+        let mut bytes = recording().to_bytes();
+        bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&7u16.to_le_bytes());
+        assert_eq!(
+            Recording::<String>::parse(&bytes),
+            Err(RecordError::UnsupportedVersion(7))
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = recording().to_bytes();
+        assert_eq!(
+            Recording::<String>::parse(&bytes[..MAGIC.len() + 1]),
+            Err(RecordError::Truncated)
+        );
+        for end in MAGIC.len() + 3..bytes.len() {
+            //Cutting at the end of an entry leaves a shorter valid recording.
+            if let Err(e) = Recording::<String>::parse(&bytes[..end]) {
+                assert_eq!(e, RecordError::Truncated);
+            }
+        }
+        assert_eq!(
+            Recording::<String>::parse(&bytes[..bytes.len() - 1]),
+            Err(RecordError::Truncated)
+        );
+    }
+
+    #[test]
+    fn frame_overflow() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        for _ in 0..2 {
+            write_varint(&mut bytes, u64::MAX);
+            write_varint(&mut bytes, 2);
+            bytes.extend_from_slice(b"{}");
+        }
+        assert_eq!(
+            Recording::<serde_json::Value>::parse(&bytes),
+            Err(RecordError::FrameOverflow)
+        );
+    }
+
+    #[test]
+    fn bad_message() {
+        let mut r = Recorder::new(0);
+        r.record(3, &5u32);
+        assert!(matches!(
+            Recording::<String>::parse(r.as_bytes()),
+            Err(RecordError::Message { frame: 3, .. })
+        ));
+    }
+}
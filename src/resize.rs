@@ -0,0 +1,207 @@
+//!
+//! Keep the size of an `OffscreenCanvas` in sync with the size of its canvas element on the page.
+//!
+//! Main thread:
+//! ```ignore
+//! let _resize = sender.observe_resize(&canvas, |e| Some(MEvent::Resize(e)));
+//! ```
+//!
+//! Worker:
+//! ```ignore
+//! MEvent::Resize(e) => {
+//!     e.apply(&canvas);
+//!     ctx.viewport(0, 0, e.width as i32, e.height as i32);
+//! }
+//! ```
+//!
+use super::*;
+
+///
+/// The new size of the canvas backing store, in device pixels.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CanvasResize {
+    pub width: u32,
+    pub height: u32,
+    ///
+    /// The size of the element in css pixels.
+    ///
+    pub css_size: [f32; 2],
+    pub device_pixel_ratio: f32,
+}
+impl CanvasResize {
+    ///
+    /// Resize the canvas. Returns false if it already had this size.
+    /// Resizing clears the canvas, so it should be redrawn afterwards.
+    ///
+    pub fn apply(&self, canvas: &web_sys::OffscreenCanvas) -> bool {
+        if canvas.width() == self.width && canvas.height() == self.height {
+            return false;
+        }
+        canvas.set_width(self.width);
+        canvas.set_height(self.height);
+        true
+    }
+}
+
+///
+/// Handle returned by [`main::MainSender::observe_resize`]. Stops observing when dropped.
+///
+pub struct ResizeListen {
+    state: Rc<RefCell<ResizeState>>,
+}
+impl Drop for ResizeListen {
+    fn drop(&mut self) {
+        self.state.borrow().observer.disconnect();
+    }
+}
+
+struct ResizeState {
+    observer: web_sys::ResizeObserver,
+    _callback: Closure<dyn FnMut(js_sys::Array)>,
+    dpr_listen: Option<gloop::EventListen<DprListen>>,
+    device_pixel_box: bool,
+    css_size: [f32; 2],
+    send: Box<dyn FnMut(CanvasResize)>,
+}
+
+pub struct DprListen {
+    state: std::rc::Weak<RefCell<ResizeState>>,
+}
+impl gloop::Listen for DprListen {
+    fn call(&mut self, _: &web_sys::Event) {
+        let state = self.state.clone();
+        //Can't replace this listener from inside of itself.
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Some(state) = state.upgrade() {
+                watch_dpr(&state);
+                let mut s = state.borrow_mut();
+                //The observer already reports device pixel changes on its own.
+                if !s.device_pixel_box {
+                    let css_size = s.css_size;
+                    (s.send)(from_css(css_size));
+                }
+            }
+        });
+    }
+}
+
+fn from_css(css_size: [f32; 2]) -> CanvasResize {
+    let dpr = gloo::utils::window().device_pixel_ratio() as f32;
+    CanvasResize {
+        width: (css_size[0] * dpr).round() as u32,
+        height: (css_size[1] * dpr).round() as u32,
+        css_size,
+        device_pixel_ratio: dpr,
+    }
+}
+
+//A media query that stops matching when the device pixel ratio changes.
+fn watch_dpr(state: &Rc<RefCell<ResizeState>>) {
+    let dpr = gloo::utils::window().device_pixel_ratio();
+    let query = format!("(resolution: {}dppx)", dpr);
+    let listen = gloo::utils::window()
+        .match_media(&query)
+        .ok()
+        .flatten()
+        .map(|mql| {
+            gloop::EventListen::new(
+                &mql,
+                "change",
+                DprListen {
+                    state: Rc::downgrade(state),
+                },
+            )
+        });
+    state.borrow_mut().dpr_listen = listen;
+}
+
+fn size_of(sizes: js_sys::Array) -> Option<[f32; 2]> {
+    let size: web_sys::ResizeObserverSize = sizes.get(0).dyn_into().ok()?;
+    Some([size.inline_size() as f32, size.block_size() as f32])
+}
+
+impl<MW: Serialize + 'static> main::MainSender<MW> {
+    ///
+    /// Observe the size of the element and forward the size its backing store should have
+    /// whenever it or the device pixel ratio changes. The current size is sent right away.
+    ///
+    /// Uses `device-pixel-content-box` where available so the size is exact, otherwise
+    /// the css size multiplied by `devicePixelRatio`.
+    ///
+    pub fn observe_resize<F: FnMut(CanvasResize) -> Option<MW> + 'static>(
+        &self,
+        elem: &web_sys::Element,
+        mut func: F,
+    ) -> ResizeListen {
+        let worker = self.worker.clone();
+        let send = move |e: CanvasResize| {
+            if let Some(val) = func(e) {
+                main::post_to_worker(&worker.borrow(), &val);
+            }
+        };
+
+        let device_pixel_box =
+            js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("ResizeObserverEntry"))
+                .and_then(|e| js_sys::Reflect::get(&e, &JsValue::from_str("prototype")))
+                .and_then(|p| {
+                    js_sys::Reflect::has(&p, &JsValue::from_str("devicePixelContentBoxSize"))
+                })
+                .unwrap_or(false);
+
+        let state = Rc::new_cyclic(|weak: &std::rc::Weak<RefCell<ResizeState>>| {
+            let weak = weak.clone();
+            let callback =
+                Closure::<dyn FnMut(js_sys::Array)>::new(move |entries: js_sys::Array| {
+                    let Some(state) = weak.upgrade() else {
+                        return;
+                    };
+                    let Ok(entry) = entries.get(0).dyn_into::<web_sys::ResizeObserverEntry>()
+                    else {
+                        return;
+                    };
+
+                    let css_size = size_of(entry.content_box_size()).unwrap_or_else(|| {
+                        let r = entry.content_rect();
+                        [r.width() as f32, r.height() as f32]
+                    });
+
+                    let mut s = state.borrow_mut();
+                    s.css_size = css_size;
+
+                    let mut e = from_css(css_size);
+                    if s.device_pixel_box {
+                        if let Some([w, h]) = size_of(entry.device_pixel_content_box_size()) {
+                            e.width = w as u32;
+                            e.height = h as u32;
+                        }
+                    }
+                    (s.send)(e);
+                });
+
+            let observer =
+                web_sys::ResizeObserver::new(callback.as_ref().unchecked_ref()).unwrap_throw();
+
+            RefCell::new(ResizeState {
+                observer,
+                _callback: callback,
+                dpr_listen: None,
+                device_pixel_box,
+                css_size: [0.0; 2],
+                send: Box::new(send),
+            })
+        });
+
+        let options = web_sys::ResizeObserverOptions::new();
+        options.set_box(if device_pixel_box {
+            web_sys::ResizeObserverBoxOptions::DevicePixelContentBox
+        } else {
+            web_sys::ResizeObserverBoxOptions::ContentBox
+        });
+        state.borrow().observer.observe_with_options(elem, &options);
+
+        watch_dpr(&state);
+
+        ResizeListen { state }
+    }
+}
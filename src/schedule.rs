@@ -0,0 +1,350 @@
+//!
+//! Run callbacks after a delay, on an interval or every few frames, from within the worker loop.
+//!
+//! ```ignore
+//! let mut scheduler: Scheduler<World> = Scheduler::new();
+//! let spawner = scheduler.every(2000.0, |world| world.spawn_enemy());
+//! scheduler.every_frames(10, |world| world.update_pathfinding());
+//!
+//! loop {
+//!     let frame = frame_timer.next().await;
+//!     //...
+//!     scheduler.run(&mut world);
+//!     if world.boss_defeated {
+//!         spawner.cancel();
+//!     }
+//! }
+//! ```
+//!
+//! Callbacks only run from [`Scheduler::run`], so they happen at the same point of every
+//! frame. Due callbacks run in the order they were scheduled, and each runs at most once
+//! per call to `run`.
+//!
+use super::*;
+use std::cell::Cell;
+
+///
+/// Refers to a scheduled callback. Clones refer to the same callback.
+///
+#[derive(Debug, Clone)]
+pub struct TaskHandle {
+    cancelled: Rc<Cell<bool>>,
+}
+impl TaskHandle {
+    ///
+    /// Stop the callback from running again. Can be called from inside of a callback.
+    ///
+    pub fn cancel(&self) {
+        self.cancelled.set(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.get()
+    }
+}
+
+enum When {
+    Time { due: f64, interval: Option<f64> },
+    Frames { due: u64, interval: Option<u64> },
+}
+
+struct Task<T> {
+    when: When,
+    cancelled: Rc<Cell<bool>>,
+    func: Box<dyn FnMut(&mut T)>,
+}
+
+///
+/// Callbacks that are passed a `&mut T` when they run. Time is measured with a [`clock::Clock`].
+///
+pub struct Scheduler<T = (), C = clock::WorkerClock> {
+    clock: C,
+    frame: u64,
+    tasks: Vec<Task<T>>,
+}
+
+impl<T> Scheduler<T> {
+    ///
+    /// A scheduler using the clock of the worker.
+    ///
+    /// # Panics
+    ///
+    /// If not called from within a webworker. Use [`Scheduler::with_clock`] elsewhere.
+    ///
+    //No Default, since it would panic outside of a worker.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self::with_clock(clock::WorkerClock::new())
+    }
+}
+
+impl<T, C: clock::Clock> Scheduler<T, C> {
+    pub fn with_clock(clock: C) -> Self {
+        Scheduler {
+            clock,
+            frame: 0,
+            tasks: vec![],
+        }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    ///
+    /// The number of times [`Scheduler::run`] was called.
+    ///
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    fn add(&mut self, when: When, func: impl FnMut(&mut T) + 'static) -> TaskHandle {
+        let cancelled = Rc::new(Cell::new(false));
+        self.tasks.push(Task {
+            when,
+            cancelled: cancelled.clone(),
+            func: Box::new(func),
+        });
+        TaskHandle { cancelled }
+    }
+
+    ///
+    /// Run once after `ms` milliseconds.
+    ///
+    pub fn after(&mut self, ms: f64, func: impl FnMut(&mut T) + 'static) -> TaskHandle {
+        let due = self.clock.now() + ms;
+        self.add(
+            When::Time {
+                due,
+                interval: None,
+            },
+            func,
+        )
+    }
+
+    ///
+    /// Run every `ms` milliseconds, starting `ms` from now. If the loop falls behind,
+    /// missed runs are skipped rather than run back to back.
+    ///
+    pub fn every(&mut self, ms: f64, func: impl FnMut(&mut T) + 'static) -> TaskHandle {
+        assert!(ms > 0.0);
+        let due = self.clock.now() + ms;
+        self.add(
+            When::Time {
+                due,
+                interval: Some(ms),
+            },
+            func,
+        )
+    }
+
+    ///
+    /// Run once after `frames` more calls to [`Scheduler::run`]. 0 and 1 both mean the next one.
+    ///
+    pub fn after_frames(&mut self, frames: u64, func: impl FnMut(&mut T) + 'static) -> TaskHandle {
+        let due = self.frame + frames.max(1);
+        self.add(
+            When::Frames {
+                due,
+                interval: None,
+            },
+            func,
+        )
+    }
+
+    ///
+    /// Run every `frames` calls to [`Scheduler::run`], starting `frames` from now.
+    ///
+    pub fn every_frames(&mut self, frames: u64, func: impl FnMut(&mut T) + 'static) -> TaskHandle {
+        assert!(frames > 0);
+        let due = self.frame + frames;
+        self.add(
+            When::Frames {
+                due,
+                interval: Some(frames),
+            },
+            func,
+        )
+    }
+
+    ///
+    /// Advance a frame and run every callback that is due.
+    ///
+    pub fn run(&mut self, ctx: &mut T) {
+        self.frame += 1;
+        let now = self.clock.now();
+        let frame = self.frame;
+
+        for task in self.tasks.iter_mut() {
+            if task.cancelled.get() {
+                continue;
+            }
+            let (due, repeat) = match &mut task.when {
+                When::Time { due, interval } => {
+                    let is_due = *due <= now;
+                    if let (true, Some(i)) = (is_due, *interval) {
+                        while *due <= now {
+                            *due += i;
+                        }
+                    }
+                    (is_due, interval.is_some())
+                }
+                When::Frames { due, interval } => {
+                    let is_due = *due <= frame;
+                    if let (true, Some(i)) = (is_due, *interval) {
+                        *due = frame + i;
+                    }
+                    (is_due, interval.is_some())
+                }
+            };
+
+            if due {
+                (task.func)(ctx);
+                if !repeat {
+                    task.cancelled.set(true);
+                }
+            }
+        }
+
+        self.tasks.retain(|t| !t.cancelled.get());
+    }
+
+    ///
+    /// The number of callbacks that will still run.
+    ///
+    pub fn len(&self) -> usize {
+        self.tasks.iter().filter(|t| !t.cancelled.get()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn cancel_all(&mut self) {
+        for t in self.tasks.drain(..) {
+            t.cancelled.set(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::MockClock;
+
+    fn scheduler() -> (MockClock, Scheduler<u32, MockClock>) {
+        let clock = MockClock::new();
+        (clock.clone(), Scheduler::with_clock(clock))
+    }
+
+    #[test]
+    fn after() {
+        let (clock, mut s) = scheduler();
+        s.after(100.0, |n| *n += 1);
+        let mut n = 0;
+
+        clock.advance(99.0);
+        s.run(&mut n);
+        assert_eq!(n, 0);
+
+        clock.advance(1.0);
+        s.run(&mut n);
+        assert_eq!(n, 1);
+        assert!(s.is_empty());
+
+        clock.advance(1000.0);
+        s.run(&mut n);
+        assert_eq!(n, 1);
+    }
+
+    #[test]
+    fn every() {
+        let (clock, mut s) = scheduler();
+        s.every(100.0, |n| *n += 1);
+        let mut n = 0;
+
+        for _ in 0..3 {
+            clock.advance(100.0);
+            s.run(&mut n);
+        }
+        assert_eq!(n, 3);
+
+        //Falling behind by several intervals runs once, not once per interval.
+        clock.advance(350.0);
+        s.run(&mut n);
+        assert_eq!(n, 4);
+
+        //And stays on the original schedule.
+        clock.advance(49.0);
+        s.run(&mut n);
+        assert_eq!(n, 4);
+        clock.advance(1.0);
+        s.run(&mut n);
+        assert_eq!(n, 5);
+        assert_eq!(s.len(), 1);
+    }
+
+    #[test]
+    fn after_frames() {
+        let (_, mut s) = scheduler();
+        let mut n = 0;
+        s.after_frames(0, |n| *n += 1);
+        s.after_frames(2, |n| *n += 10);
+
+        s.run(&mut n);
+        assert_eq!(n, 1);
+        s.run(&mut n);
+        assert_eq!(n, 11);
+        s.run(&mut n);
+        assert_eq!(n, 11);
+        assert!(s.is_empty());
+        assert_eq!(s.frame(), 3);
+    }
+
+    #[test]
+    fn every_frames() {
+        let (_, mut s) = scheduler();
+        let mut n = 0;
+        s.every_frames(3, |n| *n += 1);
+        for _ in 0..9 {
+            s.run(&mut n);
+        }
+        assert_eq!(n, 3);
+    }
+
+    #[test]
+    fn cancel_from_callback() {
+        let (_, mut s) = scheduler();
+        let handle: Rc<RefCell<Option<TaskHandle>>> = Rc::new(RefCell::new(None));
+        let h = handle.clone();
+        let task = s.every_frames(1, move |n| {
+            *n += 1;
+            if *n == 2 {
+                h.borrow().as_ref().unwrap().cancel();
+            }
+        });
+        *handle.borrow_mut() = Some(task.clone());
+
+        let mut n = 0;
+        for _ in 0..5 {
+            s.run(&mut n);
+        }
+        assert_eq!(n, 2);
+        assert!(task.is_cancelled());
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn cancel_all() {
+        let (clock, mut s) = scheduler();
+        let a = s.after(10.0, |n| *n += 1);
+        s.every_frames(1, |n| *n += 1);
+        s.cancel_all();
+        assert!(a.is_cancelled());
+
+        let mut n = 0;
+        clock.advance(10.0);
+        s.run(&mut n);
+        assert_eq!(n, 0);
+    }
+}
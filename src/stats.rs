@@ -0,0 +1,414 @@
+//!
+//! Frame pacing statistics for the worker loop.
+//!
+//! ```ignore
+//! let mut stats = FrameStats::new(1000.0 / 60.0).with_report_interval(1000.0);
+//! loop {
+//!     let frame = frame_timer.next().await;
+//!     stats.start_frame(frame.info().time);
+//!     update();
+//!     stats.start_render(clock.now());
+//!     render();
+//!     stats.end_frame(clock.now());
+//!
+//!     //Post a summary to the main thread once a second.
+//!     if let Some(s) = stats.poll_report(clock.now()) {
+//!         sender.post_message(WEvent::Stats(s));
+//!     }
+//! }
+//! ```
+//!
+//! Times are in milliseconds. A frame's time is measured from its start to the start of
+//! the next frame. Whatever isn't spent in update or render is counted as waiting.
+//!
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+///
+/// Width of each histogram bucket in milliseconds.
+///
+pub const BUCKET_MS: f64 = 2.0;
+
+///
+/// Number of histogram buckets. The last one also counts every longer frame.
+///
+pub const BUCKETS: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    time: f64,
+    frame: f64,
+    update: f64,
+    render: f64,
+}
+
+///
+/// Average time per frame spent in each part of the loop.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct PhaseTimes {
+    pub update: f64,
+    pub render: f64,
+    pub wait: f64,
+}
+
+///
+/// A summary of the frames in the window, e.g. to draw an overlay or send to the main thread.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct StatsSnapshot {
+    pub fps: f64,
+    pub average_frame: f64,
+    pub worst_frame: f64,
+    ///
+    /// Frames over budget since the stats were created or reset, see [`FrameStats::over_budget`].
+    ///
+    pub over_budget: u64,
+    pub frames: u64,
+    pub phases: PhaseTimes,
+    ///
+    /// Frame times of the window, see [`BUCKET_MS`].
+    ///
+    pub histogram: Vec<u32>,
+}
+
+///
+/// Collects frame timings over a rolling window.
+///
+#[derive(Debug, Clone)]
+pub struct FrameStats {
+    budget: f64,
+    window: f64,
+    report_interval: Option<f64>,
+    last_report: Option<f64>,
+    samples: VecDeque<Sample>,
+    frame_start: Option<f64>,
+    render_start: Option<f64>,
+    //Update and render time of the frame in progress.
+    current: (f64, f64),
+    //Whether start_render or end_frame was called for the frame in progress.
+    marked: bool,
+    over_budget: u64,
+    frames: u64,
+}
+
+impl FrameStats {
+    ///
+    /// `budget` is the longest the work of a frame should take, e.g. `1000.0 / 60.0`.
+    ///
+    pub fn new(budget: f64) -> Self {
+        FrameStats {
+            budget,
+            window: 5000.0,
+            report_interval: None,
+            last_report: None,
+            samples: VecDeque::new(),
+            frame_start: None,
+            render_start: None,
+            current: (0.0, 0.0),
+            marked: false,
+            over_budget: 0,
+            frames: 0,
+        }
+    }
+
+    ///
+    /// How far back the rolling statistics look. Defaults to 5 seconds.
+    ///
+    pub fn with_window(mut self, window: f64) -> Self {
+        self.window = window;
+        self
+    }
+
+    ///
+    /// How often [`FrameStats::poll_report`] returns a snapshot.
+    ///
+    pub fn with_report_interval(mut self, interval: f64) -> Self {
+        self.report_interval = Some(interval);
+        self
+    }
+
+    pub fn budget(&self) -> f64 {
+        self.budget
+    }
+
+    pub fn set_budget(&mut self, budget: f64) {
+        self.budget = budget;
+    }
+
+    ///
+    /// Mark the start of a frame, which is also the end of the previous one.
+    ///
+    pub fn start_frame(&mut self, now: f64) {
+        if let Some(start) = self.frame_start {
+            let frame = (now - start).max(0.0);
+            let (update, render) = self.current;
+            self.samples.push_back(Sample {
+                time: now,
+                frame,
+                update,
+                render,
+            });
+            self.frames += 1;
+            //Waiting for the next frame doesn't count against the budget.
+            let work = if self.marked { update + render } else { frame };
+            if work > self.budget {
+                self.over_budget += 1;
+            }
+            while let Some(s) = self.samples.front() {
+                if now - s.time > self.window {
+                    self.samples.pop_front();
+                } else {
+                    break;
+                }
+            }
+        }
+        self.frame_start = Some(now);
+        self.render_start = None;
+        self.current = (0.0, 0.0);
+        self.marked = false;
+    }
+
+    ///
+    /// Mark the end of update and the start of render.
+    ///
+    pub fn start_render(&mut self, now: f64) {
+        if let Some(start) = self.frame_start {
+            self.current.0 = (now - start).max(0.0);
+            self.render_start = Some(now);
+            self.marked = true;
+        }
+    }
+
+    ///
+    /// Mark the end of render. The rest of the frame is spent waiting.
+    ///
+    pub fn end_frame(&mut self, now: f64) {
+        match (self.frame_start, self.render_start) {
+            (_, Some(r)) => self.current.1 = (now - r).max(0.0),
+            //No render was marked, so it was all update.
+            (Some(start), None) => {
+                self.current.0 = (now - start).max(0.0);
+                self.marked = true;
+            }
+            _ => {}
+        }
+    }
+
+    ///
+    /// Frames per second over the window.
+    ///
+    pub fn fps(&self) -> f64 {
+        let total: f64 = self.samples.iter().map(|s| s.frame).sum();
+        if total > 0.0 {
+            self.samples.len() as f64 * 1000.0 / total
+        } else {
+            0.0
+        }
+    }
+
+    pub fn average_frame(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.samples.iter().map(|s| s.frame).sum::<f64>() / self.samples.len() as f64
+    }
+
+    ///
+    /// The longest frame in the window.
+    ///
+    pub fn worst_frame(&self) -> f64 {
+        self.samples.iter().map(|s| s.frame).fold(0.0, f64::max)
+    }
+
+    ///
+    /// Frames whose update and render took longer than the budget since the stats were
+    /// created or reset. If neither was marked, the whole frame time is used instead.
+    ///
+    pub fn over_budget(&self) -> u64 {
+        self.over_budget
+    }
+
+    ///
+    /// Frames measured since the stats were created or reset.
+    ///
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn phases(&self) -> PhaseTimes {
+        let n = self.samples.len();
+        if n == 0 {
+            return PhaseTimes::default();
+        }
+        let n = n as f64;
+        let update = self.samples.iter().map(|s| s.update).sum::<f64>() / n;
+        let render = self.samples.iter().map(|s| s.render).sum::<f64>() / n;
+        PhaseTimes {
+            update,
+            render,
+            wait: (self.average_frame() - update - render).max(0.0),
+        }
+    }
+
+    pub fn histogram(&self) -> [u32; BUCKETS] {
+        let mut h = [0; BUCKETS];
+        for s in self.samples.iter() {
+            let i = ((s.frame / BUCKET_MS) as usize).min(BUCKETS - 1);
+            h[i] += 1;
+        }
+        h
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            fps: self.fps(),
+            average_frame: self.average_frame(),
+            worst_frame: self.worst_frame(),
+            over_budget: self.over_budget,
+            frames: self.frames,
+            phases: self.phases(),
+            histogram: self.histogram().to_vec(),
+        }
+    }
+
+    ///
+    /// Returns a snapshot if the report interval has passed since the last one.
+    /// Always `None` if no interval was set.
+    ///
+    pub fn poll_report(&mut self, now: f64) -> Option<StatsSnapshot> {
+        let interval = self.report_interval?;
+        match self.last_report {
+            Some(last) if now - last < interval => None,
+            _ => {
+                self.last_report = Some(now);
+                Some(self.snapshot())
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.frame_start = None;
+        self.render_start = None;
+        self.current = (0.0, 0.0);
+        self.marked = false;
+        self.over_budget = 0;
+        self.frames = 0;
+        self.last_report = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUDGET: f64 = 1000.0 / 60.0;
+
+    //Run frames starting at each time, each spending `update` and `render` ms working.
+    fn run(stats: &mut FrameStats, starts: &[f64], update: f64, render: f64) {
+        for &t in starts {
+            stats.start_frame(t);
+            stats.start_render(t + update);
+            stats.end_frame(t + update + render);
+        }
+    }
+
+    fn every(step: f64, from: f64, to: f64) -> Vec<f64> {
+        let n = ((to - from) / step).round() as usize;
+        (0..=n).map(|i| from + i as f64 * step).collect()
+    }
+
+    #[test]
+    fn fps_and_worst_frame() {
+        let mut stats = FrameStats::new(BUDGET);
+        assert_eq!(stats.fps(), 0.0);
+
+        run(&mut stats, &every(20.0, 0.0, 100.0), 1.0, 1.0);
+        assert_eq!(stats.frames(), 5);
+        assert_eq!(stats.fps(), 50.0);
+        assert_eq!(stats.average_frame(), 20.0);
+
+        run(&mut stats, &[140.0], 1.0, 1.0);
+        assert_eq!(stats.worst_frame(), 40.0);
+        assert_eq!(stats.histogram()[(20.0 / BUCKET_MS) as usize], 5);
+        assert_eq!(stats.histogram()[(40.0 / BUCKET_MS) as usize], 1);
+    }
+
+    #[test]
+    fn window_eviction() {
+        let mut stats = FrameStats::new(BUDGET).with_window(100.0);
+        run(&mut stats, &[0.0], 1.0, 1.0);
+        run(&mut stats, &every(20.0, 50.0, 150.0), 1.0, 1.0);
+        assert_eq!(stats.worst_frame(), 50.0);
+
+        //The long frame ended at 50, so it leaves the window after 150.
+        run(&mut stats, &[170.0], 1.0, 1.0);
+        assert_eq!(stats.worst_frame(), 20.0);
+        assert_eq!(stats.fps(), 50.0);
+        //Totals aren't windowed.
+        assert_eq!(stats.frames(), 7);
+    }
+
+    #[test]
+    fn phases() {
+        let mut stats = FrameStats::new(BUDGET);
+        run(&mut stats, &every(16.0, 0.0, 64.0), 4.0, 6.0);
+        assert_eq!(
+            stats.phases(),
+            PhaseTimes {
+                update: 4.0,
+                render: 6.0,
+                wait: 6.0,
+            }
+        );
+
+        //Without start_render the work is all update.
+        let mut stats = FrameStats::new(BUDGET);
+        for t in every(16.0, 0.0, 32.0) {
+            stats.start_frame(t);
+            stats.end_frame(t + 5.0);
+        }
+        assert_eq!(stats.phases().update, 5.0);
+        assert_eq!(stats.phases().render, 0.0);
+    }
+
+    #[test]
+    fn over_budget_counts_work_not_waiting() {
+        let mut stats = FrameStats::new(BUDGET);
+        //Jittery frames that take longer than the budget, but mostly wait.
+        run(&mut stats, &[0.0, 17.0, 34.5, 51.0, 68.2], 3.0, 5.0);
+        assert_eq!(stats.over_budget(), 0);
+
+        run(&mut stats, &[85.0, 110.0], 12.0, 8.0);
+        assert_eq!(stats.over_budget(), 1);
+        assert_eq!(stats.snapshot().over_budget, 1);
+
+        stats.reset();
+        assert_eq!(stats.over_budget(), 0);
+        assert_eq!(stats.frames(), 0);
+
+        //With nothing marked, the whole frame counts.
+        for t in [0.0, 20.0, 30.0] {
+            stats.start_frame(t);
+        }
+        assert_eq!(stats.over_budget(), 1);
+    }
+
+    #[test]
+    fn poll_report() {
+        let mut stats = FrameStats::new(BUDGET);
+        assert_eq!(stats.poll_report(0.0), None);
+
+        let mut stats = stats.with_report_interval(1000.0);
+        run(&mut stats, &[0.0, 20.0], 1.0, 1.0);
+        let report = stats.poll_report(20.0).unwrap();
+        assert_eq!(report.frames, 1);
+        assert_eq!(report.histogram.len(), BUCKETS);
+
+        assert_eq!(stats.poll_report(500.0), None);
+        assert_eq!(stats.poll_report(1019.0), None);
+        assert!(stats.poll_report(1020.0).is_some());
+        assert_eq!(stats.poll_report(1500.0), None);
+    }
+}
@@ -0,0 +1,268 @@
+//!
+//! Text entry through a hidden text input on the main thread, so composed text from
+//! IMEs, dead keys and on screen keyboards reaches the worker.
+//!
+//! Main thread:
+//! ```ignore
+//! let text = sender.text_input(|e| Some(MEvent::Text(e)));
+//! recv.install_command_handler(CommandHandler::new(&canvas).with_text_input(text));
+//! ```
+//!
+//! Worker:
+//! ```ignore
+//! //When a text field drawn in the worker is clicked.
+//! sender.send_command(MainCommand::SetTextInputRect([x, y, w, h]));
+//! sender.send_command(MainCommand::FocusTextInput);
+//!
+//! MEvent::Text(e) => match e {
+//!     TextEvent::Commit { text } => field.insert(&text),
+//!     TextEvent::CompositionUpdate { text } => field.set_preedit(&text),
+//!     TextEvent::CompositionEnd { .. } => field.set_preedit(""),
+//!     TextEvent::BeforeInput { input_type, .. } if input_type == "deleteContentBackward" => {
+//!         field.backspace()
+//!     }
+//!     _ => {}
+//! }
+//! ```
+//!
+use super::*;
+
+///
+/// Text typed into the hidden text input.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum TextEvent {
+    ///
+    /// The IME started composing text.
+    ///
+    CompositionStart,
+    ///
+    /// The text being composed changed. It isn't committed yet and should be shown
+    /// as a preview at the caret.
+    ///
+    CompositionUpdate {
+        text: String,
+    },
+    CompositionEnd {
+        text: String,
+    },
+    ///
+    /// Text that should be inserted at the caret. Sent after the composition ends,
+    /// or right away for text typed without composing.
+    ///
+    Commit {
+        text: String,
+    },
+    ///
+    /// An edit other than inserting text, e.g. `deleteContentBackward` or `insertLineBreak`.
+    /// `input_type` is the `InputEvent.inputType`.
+    ///
+    BeforeInput {
+        input_type: String,
+        data: Option<String>,
+    },
+    Focus,
+    Blur,
+}
+
+pub struct MyListenText {
+    elem: web_sys::HtmlTextAreaElement,
+    send: Rc<RefCell<dyn FnMut(TextEvent)>>,
+}
+impl MyListenText {
+    //Send whatever is in the input as committed text and clear it.
+    fn commit(&self) {
+        let text = self.elem.value();
+        if !text.is_empty() {
+            self.elem.set_value("");
+            (self.send.borrow_mut())(TextEvent::Commit { text });
+        }
+    }
+}
+impl gloop::Listen for MyListenText {
+    fn call(&mut self, event: &web_sys::Event) {
+        let composition_data = || {
+            event
+                .dyn_ref::<web_sys::CompositionEvent>()
+                .and_then(|e| e.data())
+                .unwrap_or_default()
+        };
+
+        let e = match event.type_().as_str() {
+            "compositionstart" => TextEvent::CompositionStart,
+            "compositionupdate" => TextEvent::CompositionUpdate {
+                text: composition_data(),
+            },
+            "compositionend" => {
+                (self.send.borrow_mut())(TextEvent::CompositionEnd {
+                    text: composition_data(),
+                });
+                self.commit();
+                return;
+            }
+            "beforeinput" => {
+                let Some(e) = event.dyn_ref::<web_sys::InputEvent>() else {
+                    return;
+                };
+                let input_type = e.input_type();
+                //Inserted text arrives through `Commit` instead.
+                if input_type == "insertText" || input_type == "insertCompositionText" {
+                    return;
+                }
+                TextEvent::BeforeInput {
+                    input_type,
+                    data: e.data(),
+                }
+            }
+            "input" => {
+                let composing = event
+                    .dyn_ref::<web_sys::InputEvent>()
+                    .map(|e| e.is_composing())
+                    .unwrap_or(false);
+                if !composing {
+                    self.commit();
+                }
+                return;
+            }
+            "focus" => TextEvent::Focus,
+            "blur" => TextEvent::Blur,
+            _ => return,
+        };
+        (self.send.borrow_mut())(e);
+    }
+}
+
+struct TextInner {
+    elem: web_sys::HtmlTextAreaElement,
+    _handles: Vec<gloop::EventListen<MyListenText>>,
+}
+impl Drop for TextInner {
+    fn drop(&mut self) {
+        self.elem.remove();
+    }
+}
+
+///
+/// A hidden text input created by [`main::MainSender::text_input`].
+/// Clones refer to the same input, which is removed once all of them are dropped.
+///
+#[derive(Clone)]
+pub struct TextInput {
+    inner: Rc<TextInner>,
+}
+
+impl std::fmt::Debug for TextInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TextInput")
+            .field("elem", &self.inner.elem)
+            .finish()
+    }
+}
+
+impl TextInput {
+    pub fn element(&self) -> &web_sys::HtmlTextAreaElement {
+        &self.inner.elem
+    }
+
+    ///
+    /// Focus the input. On mobile this only brings up the on screen keyboard
+    /// in response to a user gesture.
+    ///
+    pub fn focus(&self) -> Result<(), JsValue> {
+        self.inner.elem.focus()
+    }
+
+    pub fn blur(&self) -> Result<(), JsValue> {
+        self.inner.elem.blur()
+    }
+
+    ///
+    /// Move the input over `[x, y, width, height]`, in css pixels relative to `relative_to`.
+    /// IMEs show their candidate window next to it.
+    ///
+    pub fn set_rect(&self, relative_to: &web_sys::Element, rect: [f32; 4]) -> Result<(), JsValue> {
+        let r = relative_to.get_bounding_client_rect();
+        let [x, y, w, h] = rect;
+        let style = self.inner.elem.style();
+        style.set_property("left", &format!("{}px", r.left() as f32 + x))?;
+        style.set_property("top", &format!("{}px", r.top() as f32 + y))?;
+        style.set_property("width", &format!("{}px", w))?;
+        style.set_property("height", &format!("{}px", h))?;
+        style.set_property("font-size", &format!("{}px", h))
+    }
+}
+
+const HIDDEN_STYLE: &str = "position: fixed; left: 0px; top: 0px; width: 1px; height: 1px; \
+    opacity: 0; color: transparent; background: transparent; caret-color: transparent; \
+    border: none; outline: none; padding: 0px; margin: 0px; resize: none; overflow: hidden; \
+    white-space: nowrap; pointer-events: none;";
+
+impl<MW: Serialize + 'static> main::MainSender<MW> {
+    ///
+    /// Create a hidden text input and forward what is typed into it as [`TextEvent`]s
+    /// mapped through the function. The worker controls it with the text input
+    /// [`command::MainCommand`]s once it is given to the [`command::CommandHandler`].
+    ///
+    pub fn text_input<F: FnMut(TextEvent) -> Option<MW> + 'static>(
+        &self,
+        mut func: F,
+    ) -> TextInput {
+        let worker = self.worker.clone();
+        let send: Rc<RefCell<dyn FnMut(TextEvent)>> = Rc::new(RefCell::new(move |e| {
+            if let Some(val) = func(e) {
+                main::post_to_worker(&worker.borrow(), &val);
+            }
+        }));
+
+        let document = gloo::utils::document();
+        let elem: web_sys::HtmlTextAreaElement = document
+            .create_element("textarea")
+            .unwrap_throw()
+            .dyn_into()
+            .unwrap_throw();
+        for (k, v) in [
+            ("style", HIDDEN_STYLE),
+            ("autocomplete", "off"),
+            ("autocapitalize", "off"),
+            ("autocorrect", "off"),
+            ("spellcheck", "false"),
+            ("aria-hidden", "true"),
+        ] {
+            elem.set_attribute(k, v).unwrap_throw();
+        }
+        document
+            .body()
+            .unwrap_throw()
+            .append_child(&elem)
+            .unwrap_throw();
+
+        let handles = [
+            "beforeinput",
+            "input",
+            "compositionstart",
+            "compositionupdate",
+            "compositionend",
+            "focus",
+            "blur",
+        ]
+        .into_iter()
+        .map(|event_type| {
+            gloop::EventListen::new(
+                &elem,
+                event_type,
+                MyListenText {
+                    elem: elem.clone(),
+                    send: send.clone(),
+                },
+            )
+        })
+        .collect();
+
+        TextInput {
+            inner: Rc::new(TextInner {
+                elem,
+                _handles: handles,
+            }),
+        }
+    }
+}
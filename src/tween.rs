@@ -0,0 +1,665 @@
+//!
+//! Animate values over time with easing curves, sequences, parallel groups, loops and yoyo.
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize, Clone, PartialEq)]
+//! enum Field {
+//!     Pos,
+//!     Color,
+//! }
+//!
+//! let anim = Anim::sequence(vec![
+//!     Anim::tween(Field::Pos, [0.0, 0.0, 0.0, 0.0], [100.0, 0.0, 0.0, 0.0], 0.5, Ease::CubicOut),
+//!     Anim::parallel(vec![
+//!         Anim::tween(Field::Pos, [100.0, 0.0, 0.0, 0.0], [100.0, 50.0, 0.0, 0.0], 1.0, Ease::SineInOut),
+//!         Anim::tween(Field::Color, RED, BLUE, 1.0, Ease::Linear),
+//!     ]),
+//! ])
+//! .repeat(Repeat::Forever, true);
+//!
+//! let mut player = Animation::new(anim);
+//! loop {
+//!     let frame = frame_timer.next().await;
+//!     player.advance(frame.info().delta_secs());
+//!     player.sample(|field, v| match field {
+//!         Field::Pos => sprite.pos = [v[0], v[1]],
+//!         Field::Color => sprite.color = v,
+//!     });
+//! }
+//! ```
+//!
+//! Times are in seconds. An [`Anim`] is a description that can be sampled at any time,
+//! so the only state of an [`Animation`] is how far it has played. Both can be saved with serde.
+//! Colors can be animated as `[f32; 4]`.
+//!
+use serde::{Deserialize, Serialize};
+
+///
+/// A value that can be interpolated.
+///
+pub trait Lerp: Clone {
+    ///
+    /// `t` is usually in `[0,1]`, but can be outside of it for easing curves that overshoot.
+    ///
+    fn lerp(&self, to: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        self + (to - self) * t
+    }
+}
+
+impl Lerp for f64 {
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        self + (to - self) * t as f64
+    }
+}
+
+impl<const N: usize> Lerp for [f32; N] {
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        let mut out = *self;
+        for (a, b) in out.iter_mut().zip(to.iter()) {
+            *a = a.lerp(b, t);
+        }
+        out
+    }
+}
+
+///
+/// An easing curve, mapping linear progress in `[0,1]` to eased progress.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Ease {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    QuartIn,
+    QuartOut,
+    QuartInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+    ExpoIn,
+    ExpoOut,
+    ExpoInOut,
+    ///
+    /// Pulls back slightly before moving, overshooting below 0.
+    ///
+    BackIn,
+    BackOut,
+    BackInOut,
+    ElasticIn,
+    ElasticOut,
+    BounceIn,
+    BounceOut,
+    BounceInOut,
+    ///
+    /// Jumps from start to end when progress reaches 1.
+    ///
+    Step,
+}
+
+fn bounce_out(t: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+    if t < 1.0 / D {
+        N * t * t
+    } else if t < 2.0 / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
+
+//Mirror an ease in curve to make the in out version.
+fn in_out(t: f32, ease_in: impl Fn(f32) -> f32) -> f32 {
+    if t < 0.5 {
+        ease_in(t * 2.0) / 2.0
+    } else {
+        1.0 - ease_in((1.0 - t) * 2.0) / 2.0
+    }
+}
+
+impl Ease {
+    pub fn apply(self, t: f32) -> f32 {
+        use std::f32::consts::PI;
+        const BACK: f32 = 1.70158;
+
+        let t = t.clamp(0.0, 1.0);
+        let quad = |t: f32| t * t;
+        let cubic = |t: f32| t * t * t;
+        let quart = |t: f32| t * t * t * t;
+        let sine = |t: f32| 1.0 - (t * PI / 2.0).cos();
+        let expo = |t: f32| {
+            if t == 0.0 {
+                0.0
+            } else {
+                2f32.powf(10.0 * t - 10.0)
+            }
+        };
+        let back = |t: f32| (BACK + 1.0) * t * t * t - BACK * t * t;
+        let bounce_in = |t: f32| 1.0 - bounce_out(1.0 - t);
+        let out = |f: &dyn Fn(f32) -> f32, t: f32| 1.0 - f(1.0 - t);
+
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => quad(t),
+            Ease::QuadOut => out(&quad, t),
+            Ease::QuadInOut => in_out(t, quad),
+            Ease::CubicIn => cubic(t),
+            Ease::CubicOut => out(&cubic, t),
+            Ease::CubicInOut => in_out(t, cubic),
+            Ease::QuartIn => quart(t),
+            Ease::QuartOut => out(&quart, t),
+            Ease::QuartInOut => in_out(t, quart),
+            Ease::SineIn => sine(t),
+            Ease::SineOut => out(&sine, t),
+            Ease::SineInOut => in_out(t, sine),
+            Ease::ExpoIn => expo(t),
+            Ease::ExpoOut => out(&expo, t),
+            Ease::ExpoInOut => in_out(t, expo),
+            Ease::BackIn => back(t),
+            Ease::BackOut => out(&back, t),
+            Ease::BackInOut => in_out(t, back),
+            Ease::ElasticIn | Ease::ElasticOut if t == 0.0 || t == 1.0 => t,
+            Ease::ElasticIn => {
+                -(2f32.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * (2.0 * PI / 3.0)).sin()
+            }
+            Ease::ElasticOut => {
+                2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+            }
+            Ease::BounceIn => bounce_in(t),
+            Ease::BounceOut => bounce_out(t),
+            Ease::BounceInOut => in_out(t, bounce_in),
+            Ease::Step => {
+                if t >= 1.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+///
+/// Animates a value from `from` to `to` over `duration` seconds.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tween<T> {
+    pub from: T,
+    pub to: T,
+    pub duration: f32,
+    pub ease: Ease,
+}
+
+impl<T: Lerp> Tween<T> {
+    pub fn new(from: T, to: T, duration: f32, ease: Ease) -> Self {
+        Tween {
+            from,
+            to,
+            duration,
+            ease,
+        }
+    }
+
+    ///
+    /// The value `time` seconds in. Times outside of the tween are clamped.
+    ///
+    pub fn value_at(&self, time: f32) -> T {
+        let t = if self.duration > 0.0 {
+            time / self.duration
+        } else {
+            1.0
+        };
+        self.from.lerp(&self.to, self.ease.apply(t))
+    }
+}
+
+///
+/// How many times a repeated animation plays.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    Times(u32),
+    Forever,
+}
+
+///
+/// A description of an animation. Each tween writes to a key, so parallel groups can
+/// animate several values at once. Use `()` as the key when only one value is animated.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Anim<T, K = ()> {
+    Tween {
+        key: K,
+        tween: Tween<T>,
+    },
+    ///
+    /// Do nothing for a number of seconds.
+    ///
+    Delay(f32),
+    ///
+    /// Play one after the other.
+    ///
+    Sequence(Vec<Anim<T, K>>),
+    ///
+    /// Play all at once. Lasts as long as the longest.
+    ///
+    Parallel(Vec<Anim<T, K>>),
+    ///
+    /// Play a number of times. With `yoyo`, every other repetition plays backwards.
+    ///
+    Repeat {
+        anim: Box<Anim<T, K>>,
+        repeat: Repeat,
+        yoyo: bool,
+    },
+}
+
+impl<T: Lerp, K> Anim<T, K> {
+    pub fn tween(key: K, from: T, to: T, duration: f32, ease: Ease) -> Self {
+        Anim::Tween {
+            key,
+            tween: Tween::new(from, to, duration, ease),
+        }
+    }
+
+    pub fn sequence(anims: Vec<Anim<T, K>>) -> Self {
+        Anim::Sequence(anims)
+    }
+
+    pub fn parallel(anims: Vec<Anim<T, K>>) -> Self {
+        Anim::Parallel(anims)
+    }
+
+    pub fn repeat(self, repeat: Repeat, yoyo: bool) -> Self {
+        Anim::Repeat {
+            anim: Box::new(self),
+            repeat,
+            yoyo,
+        }
+    }
+
+    ///
+    /// The length in seconds, or `None` if it repeats forever.
+    ///
+    pub fn duration(&self) -> Option<f32> {
+        match self {
+            Anim::Tween { tween, .. } => Some(tween.duration.max(0.0)),
+            Anim::Delay(d) => Some(d.max(0.0)),
+            Anim::Sequence(v) => v.iter().map(|a| a.duration()).sum(),
+            Anim::Parallel(v) => v
+                .iter()
+                .map(|a| a.duration())
+                .try_fold(0.0f32, |acc, d| d.map(|d| acc.max(d))),
+            Anim::Repeat { anim, repeat, .. } => match repeat {
+                Repeat::Times(n) => anim.duration().map(|d| d * *n as f32),
+                Repeat::Forever => None,
+            },
+        }
+    }
+
+    ///
+    /// Pass the value of every tween that has started by `time` to the function.
+    /// Tweens that have finished pass their final value. When a key is written more
+    /// than once, the last write is the current value.
+    ///
+    pub fn sample(&self, time: f32, out: &mut impl FnMut(&K, T)) {
+        let time = time.max(0.0);
+        match self {
+            Anim::Tween { key, tween } => out(key, tween.value_at(time)),
+            Anim::Delay(_) => {}
+            Anim::Sequence(v) => {
+                let mut start = 0.0;
+                for a in v {
+                    if time < start {
+                        break;
+                    }
+                    let Some(d) = a.duration() else {
+                        a.sample(time - start, out);
+                        break;
+                    };
+                    a.sample((time - start).min(d), out);
+                    start += d;
+                }
+            }
+            Anim::Parallel(v) => {
+                for a in v {
+                    let local = a.duration().map(|d| time.min(d)).unwrap_or(time);
+                    a.sample(local, out);
+                }
+            }
+            Anim::Repeat { anim, repeat, yoyo } => {
+                let d = match anim.duration() {
+                    Some(d) if d > 0.0 => d,
+                    //Nothing to repeat.
+                    _ => return anim.sample(time, out),
+                };
+                let mut iteration = (time / d).floor() as u64;
+                let mut local = time - iteration as f32 * d;
+                if let Repeat::Times(n) = *repeat {
+                    if n == 0 {
+                        return;
+                    }
+                    if iteration >= n as u64 {
+                        iteration = n as u64 - 1;
+                        local = d;
+                    }
+                }
+                if *yoyo && iteration % 2 == 1 {
+                    local = d - local;
+                }
+                anim.sample(local, out);
+            }
+        }
+    }
+}
+
+///
+/// Plays an [`Anim`], advanced by the delta time of the loop.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Animation<T, K = ()> {
+    pub anim: Anim<T, K>,
+    pub time: f32,
+    ///
+    /// Multiplies the delta time. Defaults to 1.
+    ///
+    pub speed: f32,
+}
+
+impl<T: Lerp, K> Animation<T, K> {
+    pub fn new(anim: Anim<T, K>) -> Self {
+        Animation {
+            anim,
+            time: 0.0,
+            speed: 1.0,
+        }
+    }
+
+    ///
+    /// Advance by `dt` seconds. Returns true once finished.
+    ///
+    pub fn advance(&mut self, dt: f32) -> bool {
+        self.time += dt * self.speed;
+        if let Some(d) = self.anim.duration() {
+            self.time = self.time.min(d);
+        }
+        self.is_finished()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.anim.duration().is_some_and(|d| self.time >= d)
+    }
+
+    pub fn reset(&mut self) {
+        self.time = 0.0;
+    }
+
+    ///
+    /// Pass the current values to the function. See [`Anim::sample`].
+    ///
+    pub fn sample(&self, mut out: impl FnMut(&K, T)) {
+        self.anim.sample(self.time, &mut out);
+    }
+
+    ///
+    /// The current value of the last tween written to.
+    /// Mostly useful when a single value is animated.
+    ///
+    pub fn value(&self) -> Option<T> {
+        let mut value = None;
+        self.sample(|_, v| value = Some(v));
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EASES: [Ease; 25] = [
+        Ease::Linear,
+        Ease::QuadIn,
+        Ease::QuadOut,
+        Ease::QuadInOut,
+        Ease::CubicIn,
+        Ease::CubicOut,
+        Ease::CubicInOut,
+        Ease::QuartIn,
+        Ease::QuartOut,
+        Ease::QuartInOut,
+        Ease::SineIn,
+        Ease::SineOut,
+        Ease::SineInOut,
+        Ease::ExpoIn,
+        Ease::ExpoOut,
+        Ease::ExpoInOut,
+        Ease::BackIn,
+        Ease::BackOut,
+        Ease::BackInOut,
+        Ease::ElasticIn,
+        Ease::ElasticOut,
+        Ease::BounceIn,
+        Ease::BounceOut,
+        Ease::BounceInOut,
+        Ease::Step,
+    ];
+
+    //Fails to compile when a variant is added, as a reminder to add it to EASES.
+    #[allow(dead_code)]
+    fn all_eases_listed(e: Ease) {
+        match e {
+            Ease::Linear
+            | Ease::QuadIn
+            | Ease::QuadOut
+            | Ease::QuadInOut
+            | Ease::CubicIn
+            | Ease::CubicOut
+            | Ease::CubicInOut
+            | Ease::QuartIn
+            | Ease::QuartOut
+            | Ease::QuartInOut
+            | Ease::SineIn
+            | Ease::SineOut
+            | Ease::SineInOut
+            | Ease::ExpoIn
+            | Ease::ExpoOut
+            | Ease::ExpoInOut
+            | Ease::BackIn
+            | Ease::BackOut
+            | Ease::BackInOut
+            | Ease::ElasticIn
+            | Ease::ElasticOut
+            | Ease::BounceIn
+            | Ease::BounceOut
+            | Ease::BounceInOut
+            | Ease::Step => {}
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    fn line(from: f32, to: f32, duration: f32) -> Anim<f32> {
+        Anim::tween((), from, to, duration, Ease::Linear)
+    }
+
+    fn value_at(anim: &Anim<f32>, time: f32) -> Option<f32> {
+        let mut value = None;
+        anim.sample(time, &mut |_, v| value = Some(v));
+        value
+    }
+
+    #[test]
+    fn ease_endpoints() {
+        for e in EASES {
+            assert!(close(e.apply(0.0), 0.0), "{:?} at 0", e);
+            assert!(close(e.apply(1.0), 1.0), "{:?} at 1", e);
+            //Progress outside of the tween is clamped.
+            assert!(close(e.apply(-1.0), 0.0), "{:?} below 0", e);
+            assert!(close(e.apply(2.0), 1.0), "{:?} above 1", e);
+        }
+        for e in [Ease::QuadInOut, Ease::CubicInOut, Ease::SineInOut] {
+            assert!(close(e.apply(0.5), 0.5), "{:?} at 0.5", e);
+        }
+    }
+
+    #[test]
+    fn lerp() {
+        assert_eq!(2.0f32.lerp(&4.0, 0.5), 3.0);
+        assert_eq!(2.0f64.lerp(&4.0, 0.25), 2.5);
+        assert_eq!(
+            [0.0, 1.0, 0.0, 1.0].lerp(&[1.0, 0.0, 0.0, 1.0], 0.5),
+            [0.5, 0.5, 0.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn duration() {
+        let seq = Anim::sequence(vec![
+            line(0.0, 1.0, 1.0),
+            Anim::Delay(0.5),
+            line(1.0, 0.0, 2.0),
+        ]);
+        assert_eq!(seq.duration(), Some(3.5));
+
+        let par = Anim::parallel(vec![line(0.0, 1.0, 1.0), line(0.0, 1.0, 2.5)]);
+        assert_eq!(par.duration(), Some(2.5));
+
+        assert_eq!(
+            par.clone().repeat(Repeat::Times(3), false).duration(),
+            Some(7.5)
+        );
+        assert_eq!(par.clone().repeat(Repeat::Forever, true).duration(), None);
+
+        //Anything containing a forever repeat is endless.
+        let endless = Anim::sequence(vec![
+            line(0.0, 1.0, 1.0).repeat(Repeat::Forever, false),
+            seq,
+        ]);
+        assert_eq!(endless.duration(), None);
+        assert_eq!(Anim::parallel(vec![endless, par]).duration(), None);
+    }
+
+    #[test]
+    fn sequence() {
+        let seq = Anim::sequence(vec![
+            line(0.0, 10.0, 1.0),
+            Anim::Delay(1.0),
+            line(10.0, 0.0, 2.0),
+        ]);
+        assert_eq!(value_at(&seq, 0.5), Some(5.0));
+        assert_eq!(value_at(&seq, 1.5), Some(10.0));
+        assert_eq!(value_at(&seq, 3.0), Some(5.0));
+        assert_eq!(value_at(&seq, 10.0), Some(0.0));
+    }
+
+    #[test]
+    fn parallel_keys() {
+        let par: Anim<f32, u8> = Anim::parallel(vec![
+            Anim::tween(0, 0.0, 4.0, 1.0, Ease::Linear),
+            Anim::tween(1, 0.0, 1.0, 2.0, Ease::Linear),
+        ]);
+        let mut got = vec![];
+        par.sample(1.5, &mut |k, v| got.push((*k, v)));
+        assert_eq!(got, vec![(0, 4.0), (1, 0.75)]);
+    }
+
+    #[test]
+    fn yoyo() {
+        let anim = line(0.0, 1.0, 1.0).repeat(Repeat::Times(3), true);
+        let at = |t| value_at(&anim, t).unwrap();
+
+        assert!(close(at(0.75), 0.75));
+        //At each boundary the value carries on from where it was.
+        assert!(close(at(1.0), 1.0));
+        assert!(close(at(1.25), 0.75));
+        assert!(close(at(2.0), 0.0));
+        assert!(close(at(2.25), 0.25));
+        //Three plays end going forwards.
+        assert!(close(at(3.0), 1.0));
+        assert!(close(at(5.0), 1.0));
+
+        //Two end going backwards.
+        let anim = line(0.0, 1.0, 1.0).repeat(Repeat::Times(2), true);
+        assert!(close(value_at(&anim, 2.0).unwrap(), 0.0));
+    }
+
+    #[test]
+    fn repeat_without_yoyo() {
+        let anim = line(0.0, 1.0, 1.0).repeat(Repeat::Forever, false);
+        assert!(close(value_at(&anim, 0.25).unwrap(), 0.25));
+        assert!(close(value_at(&anim, 1.0).unwrap(), 0.0));
+        assert!(close(value_at(&anim, 7.5).unwrap(), 0.5));
+        assert_eq!(
+            value_at(&line(0.0, 1.0, 1.0).repeat(Repeat::Times(0), false), 0.5),
+            None
+        );
+    }
+
+    #[test]
+    fn animation() {
+        let mut a = Animation::new(Anim::sequence(vec![
+            line(0.0, 10.0, 1.0),
+            line(10.0, 0.0, 1.0),
+        ]));
+        assert_eq!(a.value(), Some(0.0));
+        assert!(!a.advance(0.5));
+        assert_eq!(a.value(), Some(5.0));
+
+        a.speed = 2.0;
+        assert!(!a.advance(0.5));
+        assert_eq!(a.value(), Some(5.0));
+        assert!(a.advance(10.0));
+        assert_eq!(a.time, 2.0);
+        assert_eq!(a.value(), Some(0.0));
+
+        a.reset();
+        assert_eq!(a.value(), Some(0.0));
+        assert!(!a.is_finished());
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let anim: Anim<[f32; 4], String> = Anim::sequence(vec![
+            Anim::tween("pos".to_owned(), [0.0; 4], [1.0; 4], 1.0, Ease::BackOut),
+            Anim::Delay(0.5),
+            Anim::parallel(vec![Anim::tween(
+                "color".to_owned(),
+                [1.0, 0.0, 0.0, 1.0],
+                [0.0, 0.0, 1.0, 1.0],
+                2.0,
+                Ease::SineInOut,
+            )]),
+        ])
+        .repeat(Repeat::Times(4), true);
+
+        let mut a = Animation::new(anim);
+        a.speed = 1.5;
+        a.advance(1.2);
+
+        let json = serde_json::to_string(&a).unwrap();
+        let b: Animation<[f32; 4], String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(a, b);
+
+        let mut va = vec![];
+        let mut vb = vec![];
+        a.sample(|k, v| va.push((k.clone(), v)));
+        b.sample(|k, v| vb.push((k.clone(), v)));
+        assert_eq!(va, vb);
+    }
+}